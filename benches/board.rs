@@ -0,0 +1,118 @@
+//! Compares the array and bitboard grids behind `Board`, and threats looked up in tables with
+//! threats worked out along the lines, run with `cargo bench`.
+//!
+//! Every measurement replays the same random games, "utility", "actions", "heuristic" and "threes"
+//! also place the moves so their cost is on top of "place".
+
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use caro_ai::{ArrayGrid, BitGrid, Board, Grid, Move, Player, Rule};
+
+const GAMES: usize = 200;
+const ROUNDS: usize = 20;
+
+/// Random games played to the end, or until no move is left
+fn random_games(size: usize) -> Vec<Vec<Move>> {
+    fastrand::seed(size as u64);
+    (0..GAMES)
+        .map(|_| {
+            let mut board = Board::new(size, size);
+            let mut moves = Vec::new();
+            while board.utility(Player::X).is_none() && board.place_random().is_ok() {
+                moves.push(board.last_move().unwrap());
+            }
+            moves
+        })
+        .collect()
+}
+
+/// Time spent replaying every game ROUNDS times, calling step after each move
+fn replay<G: Grid>(size: usize, games: &[Vec<Move>], step: impl Fn(&mut Board<G>)) -> Duration {
+    replay_with(size, games, true, step)
+}
+
+/// `replay` with threats looked up or not
+fn replay_with<G: Grid>(
+    size: usize,
+    games: &[Vec<Move>],
+    lookup: bool,
+    step: impl Fn(&mut Board<G>),
+) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        for game in games {
+            let mut board = Board::<G>::with_grid(size, size, Rule::default());
+            if !lookup {
+                board = board.without_lookup();
+            }
+            for &m in game {
+                board.place(m).unwrap();
+                step(&mut board);
+            }
+            black_box(&board);
+        }
+    }
+    start.elapsed()
+}
+
+fn bench<G: Grid>(size: usize, games: &[Vec<Move>]) -> [Duration; 4] {
+    [
+        replay::<G>(size, games, |_| {}),
+        replay::<G>(size, games, |board| {
+            black_box(board.utility(Player::X));
+        }),
+        replay::<G>(size, games, |board| {
+            black_box(board.actions());
+        }),
+        replay::<G>(size, games, |board| {
+            black_box(board.heuristic(Player::X));
+        }),
+    ]
+}
+
+/// Both players' threes and then the moves to search, with threats looked up and without
+fn bench_lookup(size: usize, games: &[Vec<Move>]) -> [[Duration; 2]; 2] {
+    [true, false].map(|lookup| {
+        [
+            replay_with::<ArrayGrid>(size, games, lookup, |board| {
+                black_box(board.threes(Player::X));
+                black_box(board.threes(Player::O));
+            }),
+            replay_with::<ArrayGrid>(size, games, lookup, |board| {
+                black_box(board.actions());
+            }),
+        ]
+    })
+}
+
+fn main() {
+    for size in [15, 19] {
+        let games = random_games(size);
+        let moves: usize = games.iter().map(Vec::len).sum::<usize>() * ROUNDS;
+        println!("{size}x{size}, {moves} moves");
+        let array = bench::<ArrayGrid>(size, &games);
+        let bits = bench::<BitGrid>(size, &games);
+        let per_move = |d: Duration| d.as_nanos() as f64 / moves as f64;
+        for (i, name) in ["place", "utility", "actions", "heuristic"]
+            .into_iter()
+            .enumerate()
+        {
+            println!(
+                "  {name:<8} array {:>8.0} ns/move  bitboard {:>8.0} ns/move",
+                per_move(array[i]),
+                per_move(bits[i])
+            );
+        }
+        let [lookup, lines] = bench_lookup(size, &games);
+        for (i, name) in ["threes", "actions"].into_iter().enumerate() {
+            println!(
+                "  {name:<8} lookup {:>7.0} ns/move  lines {:>11.0} ns/move",
+                per_move(lookup[i]),
+                per_move(lines[i])
+            );
+        }
+    }
+}
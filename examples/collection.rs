@@ -0,0 +1,106 @@
+//! Opening statistics of a game collection, with the engine's evaluation of the most played
+//! openings.
+//!
+//! ```sh
+//! cargo run --release --example collection -- <file> [stones] [seconds]
+//! ```
+//! RIF databases end in `.xml` or `.rif`, RenLib libraries in `.lib` and SGF collections in
+//! `.sgf`, anything else is read as a plain game record. Every variation of a library or SGF
+//! counts as a game.
+use std::{collections::HashMap, env, fs, time::Duration};
+
+use caro_ai::{
+    record::{renlib, rif, sgf, GameRecord, RecordError},
+    Board, Move, Outcome, Player, SearchLimits, SearchTree,
+};
+
+const SHOWN: usize = 10;
+
+/// Every game of the file with its result, if known
+fn read_games(path: &str) -> Result<Vec<(Board, Option<Outcome>)>, RecordError> {
+    let extension = path.rsplit('.').next().unwrap_or("").to_lowercase();
+    let io_error = |e: std::io::Error| RecordError::Parse(e.to_string());
+    let text = || fs::read_to_string(path).map_err(io_error);
+    let mut games = Vec::new();
+    match extension.as_str() {
+        "xml" | "rif" => {
+            for game in rif::parse_rif(&text()?)? {
+                games.push((game.to_board()?, game.result));
+            }
+        }
+        "lib" => {
+            let library = renlib::parse_renlib(&fs::read(path).map_err(io_error)?)?;
+            for board in library.variation_boards()? {
+                games.push((board, None));
+            }
+        }
+        "sgf" => {
+            for game in sgf::parse_collection(&text()?)? {
+                let boards = game.variation_boards()?;
+                // the result belongs to the main line
+                for (i, board) in boards.into_iter().enumerate() {
+                    games.push((board, game.result.filter(|_| i == 0)));
+                }
+            }
+        }
+        _ => {
+            let record: GameRecord = text()?.parse()?;
+            games.push((record.to_board()?, record.result));
+        }
+    }
+    Ok(games)
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let path = args.next().expect("pass the file of the collection");
+    let stones: usize = args
+        .next()
+        .map_or(5, |arg| arg.parse().expect("stones must be a number"));
+    let seconds: f32 = args
+        .next()
+        .map_or(1.0, |arg| arg.parse().expect("seconds must be a number"));
+
+    let games = read_games(&path).unwrap_or_else(|e| panic!("unable to read {path}: {e}"));
+    // games, then wins of X, of O and draws
+    let mut openings: HashMap<Vec<Move>, (Board, usize, [usize; 3])> = HashMap::new();
+    for (board, result) in &games {
+        let opening: Vec<Move> = board.history().take(stones).collect();
+        if opening.len() < stones {
+            continue;
+        }
+        let entry = openings.entry(opening).or_insert_with(|| {
+            let mut position = board.clone();
+            while position.history().count() > stones {
+                position.undo();
+            }
+            (position, 0, [0; 3])
+        });
+        entry.1 += 1;
+        match result {
+            Some(Outcome::Win(Player::X)) => entry.2[0] += 1,
+            Some(Outcome::Win(Player::O)) => entry.2[1] += 1,
+            Some(Outcome::Draw) => entry.2[2] += 1,
+            None => {}
+        }
+    }
+    println!(
+        "{} games, {} openings of {stones} stones",
+        games.len(),
+        openings.len()
+    );
+
+    let mut openings: Vec<_> = openings.into_iter().collect();
+    openings.sort_by_key(|(_, (_, count, _))| std::cmp::Reverse(*count));
+    for (opening, (position, count, [x, o, draws])) in openings.into_iter().take(SHOWN) {
+        let to_move = position.player;
+        let result =
+            SearchTree::new(position).search(SearchLimits::time(Duration::from_secs_f32(seconds)));
+        let value = result.stats.moves.first().map_or(0.5, |best| best.value);
+        println!(
+            "{opening:?}\n  games {count:>5}  X {x:>4}  O {o:>4}  draws {draws:>4}  \
+             engine: {to_move:?} to move, best {:?} value {value:.3}",
+            result.best_move
+        );
+    }
+}
@@ -0,0 +1,66 @@
+//! Compare leaf, tree and root parallelisation on the same positions, each searching the plain tree
+//! and the DAG sharing transpositions.
+//!
+//! ```sh
+//! cargo run --release --example parallelism -- [threads] [seconds]
+//! ```
+use std::{env, time::Duration};
+
+use caro_ai::{Board, Move, Parallelism, SearchConfig, SearchLimits, SearchTree};
+
+const POSITIONS: [&[Move]; 3] = [
+    &[[7, 7]],
+    &[[7, 7], [8, 8], [8, 7], [6, 7], [9, 6]],
+    &[
+        [7, 7],
+        [7, 8],
+        [8, 8],
+        [6, 6],
+        [9, 9],
+        [10, 10],
+        [8, 6],
+        [8, 7],
+    ],
+];
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let threads = args.next().map_or(SearchConfig::default().threads, |arg| {
+        arg.parse().expect("threads must be a number")
+    });
+    let seconds: f32 = args
+        .next()
+        .map_or(2.0, |arg| arg.parse().expect("seconds must be a number"));
+
+    for moves in POSITIONS {
+        let mut board = Board::new(15, 15);
+        for &m in moves {
+            board.place(m).unwrap();
+        }
+        println!("position {moves:?}");
+        for parallelism in [Parallelism::Leaf, Parallelism::Tree, Parallelism::Root] {
+            for transpositions in [false, true] {
+                let config = SearchConfig {
+                    threads,
+                    parallelism,
+                    transpositions,
+                    ..SearchConfig::default()
+                };
+                let mut search_tree = SearchTree::with_config(board.clone(), config);
+                let limits = SearchLimits::time(Duration::from_secs_f32(seconds));
+                let result = search_tree.search(limits);
+                let best = &result.stats.moves[0];
+                println!(
+                    "  {:<5} {:<4} move {:?} visits {:>7} value {:.3} playouts/s {:>9.0} shared {:>7}",
+                    format!("{parallelism:?}"),
+                    if transpositions { "dag" } else { "tree" },
+                    best.m,
+                    best.visits,
+                    best.value,
+                    result.stats.playouts_per_second(),
+                    result.stats.transpositions
+                );
+            }
+        }
+    }
+}
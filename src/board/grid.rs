@@ -0,0 +1,428 @@
+use std::ops::Index;
+
+use ndarray::Array2;
+
+use super::{BoardValue, Direction};
+
+/// Storage for the stones of a board, indexed `[y, x]`
+pub trait Grid: Index<[usize; 2], Output = BoardValue> + Clone + Send + Sync + 'static {
+    /// Empty grid of width columns and height rows
+    fn new(width: usize, height: usize) -> Self;
+
+    fn set(&mut self, index: [usize; 2], v: BoardValue);
+
+    fn is_full(&self) -> bool;
+
+    /// Smallest box around the stones as `[min_x, min_y, max_x, max_y]`, `None` without stones
+    fn bounds(&self) -> Option<[usize; 4]>;
+
+    /// Cheap test ruling out a line of len stones through the stone at index, grids without one
+    /// always answer maybe
+    fn may_have_line(&self, _index: [usize; 2], _len: usize) -> bool {
+        true
+    }
+
+    /// A stone at one end of every line of len or more v stones with the direction of the line,
+    /// `None` if the grid can only find them by walking the lines
+    fn lines(&self, _v: BoardValue, _len: usize) -> Option<Vec<([usize; 2], Direction)>> {
+        None
+    }
+
+    /// Total length of the lines of v stones from two stones up to len - 1, leaving out those
+    /// with fewer than span points free of the opponent around them. `None` if the grid can only
+    /// count them by walking the lines
+    fn straights(&self, _v: BoardValue, _len: usize, _span: usize) -> Option<usize> {
+        None
+    }
+}
+
+/// Plain grid with one `BoardValue` per point
+pub type ArrayGrid = Array2<BoardValue>;
+
+impl Grid for ArrayGrid {
+    fn new(width: usize, height: usize) -> Self {
+        Array2::from_elem((height, width), BoardValue::Empty)
+    }
+
+    fn set(&mut self, index: [usize; 2], v: BoardValue) {
+        self[index] = v;
+    }
+
+    fn is_full(&self) -> bool {
+        self.iter().all(|&v| v != BoardValue::Empty)
+    }
+
+    fn bounds(&self) -> Option<[usize; 4]> {
+        self.indexed_iter()
+            .filter(|(_, &v)| v != BoardValue::Empty)
+            .map(|((y, x), _)| [x, y, x, y])
+            .reduce(|a, b| {
+                [
+                    a[0].min(b[0]),
+                    a[1].min(b[1]),
+                    a[2].max(b[2]),
+                    a[3].max(b[3]),
+                ]
+            })
+    }
+}
+
+/// Grid keeping a bitset per player, bit `y * stride + x` is the point (x, y). Every row ends with
+/// an always empty padding bit so lines can't run from one row into the next
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct BitGrid {
+    width: usize,
+    height: usize,
+    stride: usize,
+    x: Vec<u64>,
+    o: Vec<u64>,
+    /// Bits of the points on the grid, without the padding
+    points: Vec<u64>,
+}
+impl BitGrid {
+    fn bit(&self, [y, x]: [usize; 2]) -> usize {
+        assert!(
+            x < self.width && y < self.height,
+            "[{y}, {x}] is off the grid"
+        );
+        y * self.stride + x
+    }
+
+    fn test(bits: &[u64], i: usize) -> bool {
+        bits[i / 64] >> (i % 64) & 1 == 1
+    }
+
+    fn bits(&self, v: BoardValue) -> Option<&Vec<u64>> {
+        match v {
+            BoardValue::X => Some(&self.x),
+            BoardValue::O => Some(&self.o),
+            BoardValue::Empty => None,
+        }
+    }
+
+    /// The four line directions as bit steps, the padding bit stops every one of them at the end
+    /// of a row. The last one goes back along the row, so it runs against `AntiDiagonal`
+    fn steps(&self) -> [(usize, Direction); 4] {
+        [
+            (1, Direction::Horizontal),
+            (self.stride, Direction::Vertical),
+            (self.stride + 1, Direction::Diagonal),
+            (self.stride - 1, Direction::AntiDiagonal),
+        ]
+    }
+
+    /// Bits starting len set bits in a row along step
+    fn windows(bits: &[u64], step: usize, len: usize) -> Vec<u64> {
+        let mut windows = bits.to_vec();
+        for t in 1..len {
+            let shifted = shift_down(bits, t * step);
+            windows.iter_mut().zip(shifted).for_each(|(w, s)| *w &= s);
+        }
+        windows
+    }
+
+    /// Bits of the first point of every line of len or more set bits along step
+    fn runs(bits: &[u64], step: usize, len: usize) -> Vec<u64> {
+        let before = shift_up(bits, step);
+        let windows = Self::windows(bits, step, len);
+        windows
+            .into_iter()
+            .zip(before)
+            .map(|(w, p)| w & !p)
+            .collect()
+    }
+
+    /// Points of the set bits
+    fn indices(&self, bits: &[u64]) -> Vec<[usize; 2]> {
+        let mut indices = Vec::new();
+        for (w, &word) in bits.iter().enumerate() {
+            let mut word = word;
+            while word != 0 {
+                let i = w * 64 + word.trailing_zeros() as usize;
+                indices.push([i / self.stride, i % self.stride]);
+                word &= word - 1;
+            }
+        }
+        indices
+    }
+}
+
+/// bits shifted away from bit 0 by n, as one long integer of the same length
+fn shift_up(bits: &[u64], n: usize) -> Vec<u64> {
+    let (words, offset) = (n / 64, n % 64);
+    (0..bits.len())
+        .map(|i| {
+            let Some(i) = i.checked_sub(words) else {
+                return 0;
+            };
+            let high = bits[i] << offset;
+            let low = match (offset, i.checked_sub(1)) {
+                (1.., Some(j)) => bits[j] >> (64 - offset),
+                _ => 0,
+            };
+            high | low
+        })
+        .collect()
+}
+
+/// bits shifted towards bit 0 by n, as one long integer
+fn shift_down(bits: &[u64], n: usize) -> Vec<u64> {
+    let (words, offset) = (n / 64, n % 64);
+    (0..bits.len())
+        .map(|i| {
+            let low = bits.get(i + words).map_or(0, |w| w >> offset);
+            let high = match offset {
+                0 => 0,
+                _ => bits.get(i + words + 1).map_or(0, |w| w << (64 - offset)),
+            };
+            low | high
+        })
+        .collect()
+}
+
+impl Index<[usize; 2]> for BitGrid {
+    type Output = BoardValue;
+
+    fn index(&self, index: [usize; 2]) -> &BoardValue {
+        let i = self.bit(index);
+        if Self::test(&self.x, i) {
+            &BoardValue::X
+        } else if Self::test(&self.o, i) {
+            &BoardValue::O
+        } else {
+            &BoardValue::Empty
+        }
+    }
+}
+
+impl Grid for BitGrid {
+    fn new(width: usize, height: usize) -> Self {
+        let stride = width + 1;
+        let words = (height * stride).div_ceil(64);
+        let mut points = vec![0; words];
+        for y in 0..height {
+            for x in 0..width {
+                let i = y * stride + x;
+                points[i / 64] |= 1 << (i % 64);
+            }
+        }
+        BitGrid {
+            width,
+            height,
+            stride,
+            x: vec![0; words],
+            o: vec![0; words],
+            points,
+        }
+    }
+
+    fn set(&mut self, index: [usize; 2], v: BoardValue) {
+        let i = self.bit(index);
+        let (word, mask) = (i / 64, 1 << (i % 64));
+        self.x[word] &= !mask;
+        self.o[word] &= !mask;
+        match v {
+            BoardValue::X => self.x[word] |= mask,
+            BoardValue::O => self.o[word] |= mask,
+            BoardValue::Empty => {}
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        let stones: u32 = self
+            .x
+            .iter()
+            .chain(self.o.iter())
+            .map(|w| w.count_ones())
+            .sum();
+        stones as usize == self.width * self.height
+    }
+
+    fn bounds(&self) -> Option<[usize; 4]> {
+        let stones: Vec<u64> = self.x.iter().zip(&self.o).map(|(x, o)| x | o).collect();
+        self.indices(&stones)
+            .into_iter()
+            .map(|[y, x]| [x, y, x, y])
+            .reduce(|a, b| {
+                [
+                    a[0].min(b[0]),
+                    a[1].min(b[1]),
+                    a[2].max(b[2]),
+                    a[3].max(b[3]),
+                ]
+            })
+    }
+
+    fn may_have_line(&self, index: [usize; 2], len: usize) -> bool {
+        let Some(bits) = self.bits(self[index]) else {
+            return false;
+        };
+        let i = self.bit(index);
+        let end = self.height * self.stride;
+        for (step, _) in self.steps() {
+            let mut count = 1;
+            let mut j = i;
+            while count < len && j + step < end && Self::test(bits, j + step) {
+                j += step;
+                count += 1;
+            }
+            j = i;
+            while count < len && j >= step && Self::test(bits, j - step) {
+                j -= step;
+                count += 1;
+            }
+            if count >= len {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn lines(&self, v: BoardValue, len: usize) -> Option<Vec<([usize; 2], Direction)>> {
+        let Some(bits) = self.bits(v) else {
+            return Some(Vec::new());
+        };
+        let mut lines = Vec::new();
+        for (step, direction) in self.steps() {
+            let runs = Self::runs(bits, step, len);
+            lines.extend(self.indices(&runs).into_iter().map(|i| (i, direction)));
+        }
+        Some(lines)
+    }
+
+    fn straights(&self, v: BoardValue, len: usize, span: usize) -> Option<usize> {
+        let (Some(own), Some(opponent)) = (self.bits(v), v.opposite().and_then(|o| self.bits(o)))
+        else {
+            return Some(0);
+        };
+        let free: Vec<u64> = self
+            .points
+            .iter()
+            .zip(opponent)
+            .map(|(p, o)| p & !o)
+            .collect();
+        let mut total = 0;
+        for (step, _) in self.steps() {
+            // points of the spaces with room for span points, found from where that room starts
+            let room = Self::windows(&free, step, span);
+            let mut roomy = room.clone();
+            for t in 1..span {
+                let shifted = shift_up(&room, t * step);
+                roomy.iter_mut().zip(shifted).for_each(|(r, s)| *r |= s);
+            }
+            let mut runs: Vec<u64> = Self::runs(own, step, 1)
+                .into_iter()
+                .zip(roomy)
+                .map(|(r, s)| r & s)
+                .collect();
+            for n in 2..len {
+                // runs of n or more, less those going on past n
+                let shifted = shift_down(own, (n - 1) * step);
+                runs.iter_mut().zip(shifted).for_each(|(r, s)| *r &= s);
+                let longer = shift_down(own, n * step);
+                let exact: u32 = runs
+                    .iter()
+                    .zip(longer)
+                    .map(|(r, l)| (r & !l).count_ones())
+                    .sum();
+                total += n * exact as usize;
+            }
+        }
+        Some(total)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ArrayGrid, BitGrid, Grid};
+    use crate::board::{BoardValue, Direction};
+
+    #[test]
+    fn test_bit_grid_matches_array_grid() {
+        let (width, height) = (19, 13);
+        let mut array = ArrayGrid::new(width, height);
+        let mut bits = BitGrid::new(width, height);
+        for _ in 0..2000 {
+            let index = [fastrand::usize(..height), fastrand::usize(..width)];
+            let v = [BoardValue::X, BoardValue::O, BoardValue::Empty][fastrand::usize(..3)];
+            array.set(index, v);
+            bits.set(index, v);
+            assert_eq!(array[index], bits[index]);
+        }
+        for y in 0..height {
+            for x in 0..width {
+                assert_eq!(array[[y, x]], bits[[y, x]]);
+                array.set([y, x], BoardValue::X);
+                bits.set([y, x], BoardValue::O);
+            }
+        }
+        assert!(array.is_full() && bits.is_full());
+    }
+
+    #[test]
+    fn test_may_have_line() {
+        let mut bits = BitGrid::new(15, 15);
+        // a row ending at the right edge must not run into the next row
+        for x in 11..15 {
+            bits.set([3, x], BoardValue::X);
+        }
+        bits.set([4, 0], BoardValue::X);
+        assert!(!bits.may_have_line([3, 14], 5));
+        assert!(bits.may_have_line([3, 14], 4));
+        // anti-diagonal from the left edge
+        for t in 0..5 {
+            bits.set([10 - t, t], BoardValue::O);
+        }
+        assert!(bits.may_have_line([8, 2], 5));
+        assert!(!bits.may_have_line([8, 2], 6));
+        assert!(!bits.may_have_line([0, 0], 1));
+
+        let lines = |bits: &BitGrid, v, len| bits.lines(v, len).unwrap();
+        assert_eq!(
+            lines(&bits, BoardValue::X, 4),
+            [([3, 11], Direction::Horizontal)]
+        );
+        assert!(lines(&bits, BoardValue::X, 5).is_empty());
+        // the step along the anti-diagonal starts from the top
+        assert_eq!(
+            lines(&bits, BoardValue::O, 5),
+            [([6, 4], Direction::AntiDiagonal)]
+        );
+        assert!(lines(&bits, BoardValue::O, 6).is_empty());
+        // vertical across word boundaries
+        for y in 0..7 {
+            bits.set([y, 7], BoardValue::X);
+        }
+        assert_eq!(
+            lines(&bits, BoardValue::X, 7),
+            [([0, 7], Direction::Vertical)]
+        );
+        assert_eq!(bits.bounds(), Some([0, 0, 14, 10]));
+    }
+
+    #[test]
+    fn test_straights() {
+        // O X X O . X X X . . O, the two is walled in and the three has six points of room
+        let mut bits = BitGrid::new(15, 15);
+        let mut array = ArrayGrid::new(15, 15);
+        for (x, v) in "oxxo_xxx__o".chars().enumerate() {
+            let v = match v {
+                'x' => BoardValue::X,
+                'o' => BoardValue::O,
+                _ => continue,
+            };
+            bits.set([7, x], v);
+            array.set([7, x], v);
+        }
+        assert_eq!(bits.straights(BoardValue::X, 5, 5), Some(3));
+        assert_eq!(bits.straights(BoardValue::X, 5, 6), Some(3));
+        assert_eq!(bits.straights(BoardValue::X, 5, 7), Some(0));
+        // with a win of three only the two counts, once it has room
+        assert_eq!(bits.straights(BoardValue::X, 3, 3), Some(0));
+        assert_eq!(bits.straights(BoardValue::X, 3, 2), Some(2));
+        assert_eq!(bits.straights(BoardValue::O, 5, 5), Some(0));
+        assert_eq!(array.straights(BoardValue::X, 5, 5), None);
+        assert_eq!(bits.bounds(), array.bounds());
+        assert_eq!(ArrayGrid::new(3, 3).bounds(), None);
+    }
+}
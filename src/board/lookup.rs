@@ -0,0 +1,290 @@
+//! Threats looked up in tables instead of worked out along the line, by the shape of the
+//! win_length points on either side of a stone.
+//!
+//! A shape is a number in base 3 with a digit for each of those points, empty, the player's or
+//! closed, since the opponent's stones and the edge of the board close a line alike. Nothing
+//! further away changes the threat, so a table for the rule holds the threat of every shape,
+//! worked out once by `Line::threat` the first time the rule is played. The board keeps the
+//! shapes around every point up to date as stones are placed and taken back, which makes a
+//! threat a single lookup. Copies of the board leave them behind and read a shape off the grid
+//! when they look it up, so that the boards kept for every position of a search stay small.
+//!
+//! Tables go up to six in a row, Connect6 included, seven in a row would take 3^14 shapes and
+//! its threats are worked out along the line instead.
+
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+};
+
+use super::{patterns::Line, BoardValue, Direction, Grid, Move, Player, Rule, Threat};
+
+/// Longest win with tables, there are 3^(2k) shapes
+pub(super) const MAX_WIN_LENGTH: usize = 6;
+
+const EMPTY: u32 = 0;
+const OWN: u32 = 1;
+const CLOSED: u32 = 2;
+
+/// Threat of the stone in the middle of a shape, with a bit for each digit of a key point
+type Entry = (Option<Threat>, u16);
+
+/// Entry of every shape, for player under rule with k in a row
+fn table(rule: Rule, player: Player, k: usize) -> &'static [Entry] {
+    type Tables = HashMap<(Rule, Option<Player>, usize), &'static [Entry]>;
+    static TABLES: OnceLock<Mutex<Tables>> = OnceLock::new();
+
+    // only Renju treats the players differently
+    let key = (rule, (rule == Rule::Renju).then_some(player), k);
+    let mut tables = TABLES.get_or_init(Default::default).lock().unwrap();
+    tables.entry(key).or_insert_with(|| {
+        let shapes = (0..3usize.pow(2 * k as u32)).map(|shape| {
+            let mut cells: Vec<BoardValue> = (0..2 * k)
+                .map(|j| match (shape / 3usize.pow(j as u32)) % 3 {
+                    0 => BoardValue::Empty,
+                    1 => player.board_value(),
+                    _ => player.opponent().board_value(),
+                })
+                .collect();
+            cells.insert(k, player.board_value());
+            match Line::new(cells, player, rule, k).threat(k) {
+                Some((threat, points)) => {
+                    // the points on either side of the stone are digits k apart
+                    let bits = points.iter().map(|&i| 1 << if i < k { i } else { i - 1 });
+                    (Some(threat), bits.sum())
+                }
+                None => (None, 0),
+            }
+        });
+        Box::leak(shapes.collect())
+    })
+}
+
+/// Digit of value for player
+fn digit(value: BoardValue, player: Player) -> u32 {
+    match value {
+        BoardValue::Empty => EMPTY,
+        v if v == player.board_value() => OWN,
+        _ => CLOSED,
+    }
+}
+
+/// Tables of a board's rule, with the shape around every point along each direction, for each
+/// player
+pub(super) struct Shapes {
+    /// By point, then player, then direction, `None` on a copy of the board
+    shapes: Option<Vec<[[u32; 4]; 2]>>,
+    tables: [&'static [Entry]; 2],
+    width: usize,
+    height: usize,
+    win_length: usize,
+}
+impl Shapes {
+    /// Shapes of the stones on grid, `None` if k in a row is too long for the tables
+    pub(super) fn new<G: Grid>(
+        grid: &G,
+        width: usize,
+        height: usize,
+        rule: Rule,
+        k: usize,
+    ) -> Option<Self> {
+        if k > MAX_WIN_LENGTH {
+            return None;
+        }
+        let mut shapes = Shapes {
+            shapes: None,
+            tables: [table(rule, Player::X, k), table(rule, Player::O, k)],
+            width,
+            height,
+            win_length: k,
+        };
+        let mut points = vec![[[0; 4]; 2]; width * height];
+        for y in 0..height {
+            for x in 0..width {
+                for (p, player) in [Player::X, Player::O].into_iter().enumerate() {
+                    for (d, direction) in Direction::ALL.into_iter().enumerate() {
+                        points[y * width + x][p][d] =
+                            shapes.shape_on(grid, [x, y], direction, player);
+                    }
+                }
+            }
+        }
+        shapes.shapes = Some(points);
+        Some(shapes)
+    }
+
+    /// Threat m would make along direction if it held a stone of player
+    pub(super) fn threat<G: Grid>(
+        &self,
+        grid: &G,
+        m: Move,
+        direction: Direction,
+        player: Player,
+    ) -> Option<Threat> {
+        self.threat_closed(grid, m, direction, player, None)
+    }
+
+    /// `threat` with the empty point closed_at points further along direction closed
+    pub(super) fn threat_closed<G: Grid>(
+        &self,
+        grid: &G,
+        m: Move,
+        direction: Direction,
+        player: Player,
+        closed_at: Option<isize>,
+    ) -> Option<Threat> {
+        let mut shape = self.shape(grid, m, direction, player);
+        if let Some(t) = closed_at {
+            shape += (CLOSED - EMPTY) * self.power(t);
+        }
+        self.tables[player as usize][shape as usize].0
+    }
+
+    /// Key points of the `threat` at m
+    pub(super) fn key_points<G: Grid>(
+        &self,
+        grid: &G,
+        m: Move,
+        direction: Direction,
+        player: Player,
+    ) -> Vec<Move> {
+        let shape = self.shape(grid, m, direction, player);
+        let bits = self.tables[player as usize][shape as usize].1;
+        let k = self.win_length as isize;
+        (-k..=k)
+            .filter(|&t| t != 0 && bits & (1 << self.digit_index(t)) != 0)
+            .filter_map(|t| self.offset(m, direction, t))
+            .collect()
+    }
+
+    /// Keep the shapes around m right after its point changed from old to new
+    pub(super) fn update(&mut self, m: Move, old: BoardValue, new: BoardValue) {
+        let Some(mut shapes) = self.shapes.take() else {
+            return;
+        };
+        let k = self.win_length as isize;
+        for (d, direction) in Direction::ALL.into_iter().enumerate() {
+            for t in (-k..=k).filter(|&t| t != 0) {
+                // m is -t points from the point t points away
+                let Some([x, y]) = self.offset(m, direction, t) else {
+                    continue;
+                };
+                let power = self.power(-t);
+                for (p, player) in [Player::X, Player::O].into_iter().enumerate() {
+                    let shape = &mut shapes[y * self.width + x][p][d];
+                    *shape = *shape - digit(old, player) * power + digit(new, player) * power;
+                }
+            }
+        }
+        self.shapes = Some(shapes);
+    }
+
+    /// Shape around m along direction seen by player, kept or read off grid
+    fn shape<G: Grid>(&self, grid: &G, m: Move, direction: Direction, player: Player) -> u32 {
+        match &self.shapes {
+            Some(shapes) => shapes[m[1] * self.width + m[0]][player as usize][direction as usize],
+            None => self.shape_on(grid, m, direction, player),
+        }
+    }
+
+    /// Shape of the points of grid around m along direction, seen by player
+    fn shape_on<G: Grid>(&self, grid: &G, m: Move, direction: Direction, player: Player) -> u32 {
+        let k = self.win_length as isize;
+        (-k..=k)
+            .filter(|&t| t != 0)
+            .map(|t| {
+                let value = match self.offset(m, direction, t) {
+                    Some([x, y]) => digit(grid[[y, x]], player),
+                    None => CLOSED,
+                };
+                value * self.power(t)
+            })
+            .sum()
+    }
+
+    /// Place value of the digit for the point t points along the line
+    fn power(&self, t: isize) -> u32 {
+        3u32.pow(self.digit_index(t))
+    }
+
+    fn digit_index(&self, t: isize) -> u32 {
+        let k = self.win_length as isize;
+        (if t < 0 { t + k } else { t + k - 1 }) as u32
+    }
+
+    fn offset(&self, m: Move, direction: Direction, t: isize) -> Option<Move> {
+        let (dx, dy) = direction.step();
+        let x = m[0].checked_add_signed(dx * t)?;
+        let y = m[1].checked_add_signed(dy * t)?;
+        (x < self.width && y < self.height).then_some([x, y])
+    }
+}
+
+impl Clone for Shapes {
+    /// The tables without the shapes, which the copy reads off its grid
+    fn clone(&self) -> Self {
+        Shapes {
+            shapes: None,
+            ..*self
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::board::{Board, Direction, Player, Rule};
+
+    #[test]
+    fn test_lookup_matches_lines() {
+        for rule in [
+            Rule::default(),
+            Rule::Caro {
+                overline_wins: false,
+            },
+            Rule::Freestyle,
+            Rule::Standard,
+            Rule::Renju,
+            Rule::Connect6,
+        ] {
+            for _ in 0..10 {
+                let mut board = Board::with_rule(11, 11, rule);
+                let mut lines = Board::with_rule(11, 11, rule).without_lookup();
+                while board.result().is_none() && board.place_random().is_ok() {
+                    lines.place(board.last_move().unwrap()).unwrap();
+                    // a copy reads the shapes off its grid
+                    let copy = board.clone();
+                    assert!(copy.shapes.as_ref().unwrap().shapes.is_none());
+                    for player in [Player::X, Player::O] {
+                        assert_eq!(board.threes(player), lines.threes(player), "{board}");
+                        assert_eq!(copy.threes(player), lines.threes(player), "{board}");
+                    }
+                    for y in 0..board.height() {
+                        for x in 0..board.width() {
+                            for direction in Direction::ALL {
+                                assert_eq!(
+                                    board.threat([x, y], direction),
+                                    lines.threat([x, y], direction),
+                                    "{board} {x},{y} {direction:?}"
+                                );
+                                let (mut points, mut expected) = (
+                                    board.threat_points([x, y], direction),
+                                    lines.threat_points([x, y], direction),
+                                );
+                                points.sort();
+                                expected.sort();
+                                assert_eq!(points, expected, "{board} {x},{y} {direction:?}");
+                            }
+                        }
+                    }
+                }
+                // and after taking the moves back
+                while board.undo().is_some() {
+                    lines.undo();
+                    for player in [Player::X, Player::O] {
+                        assert_eq!(board.threes(player), lines.threes(player), "{board}");
+                    }
+                }
+            }
+        }
+    }
+}
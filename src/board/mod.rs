@@ -1,9 +1,25 @@
-use std::{fmt::Display, iter};
+use std::fmt::Display;
 
-use ndarray::{Array, Array2};
 use radix_fmt::radix;
 
+mod grid;
+mod lookup;
+mod notation;
 mod patterns;
+mod renju;
+mod rule;
+mod vcf;
+mod vct;
+mod zobrist;
+
+pub use grid::{ArrayGrid, BitGrid, Grid};
+use patterns::KnownThrees;
+pub use patterns::{Defences, Direction, Threat, Three};
+pub(crate) const WIN_LENGTHS: std::ops::RangeInclusive<usize> = 4..=7;
+
+use lookup::Shapes;
+use renju::ForbiddenChecker;
+pub use rule::{Restriction, Rule};
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum BoardValue {
     X,
@@ -37,13 +53,19 @@ impl Display for BoardValue {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum Player {
     X,
     O,
 }
 impl Player {
-    const FIRST: Player = Player::X;
+    pub const FIRST: Player = Player::X;
+    pub fn opponent(&self) -> Player {
+        match self {
+            Player::X => Player::O,
+            Player::O => Player::X,
+        }
+    }
     fn board_value(&self) -> BoardValue {
         match self {
             Player::X => BoardValue::X,
@@ -51,10 +73,7 @@ impl Player {
         }
     }
     fn next(&mut self) {
-        *self = match self {
-            Player::X => Player::O,
-            Player::O => Player::X,
-        }
+        *self = self.opponent();
     }
 }
 
@@ -66,6 +85,7 @@ pub enum PlacingError {
     OutOfBounds,
     Occupied,
     FullBoard,
+    Forbidden,
 }
 impl Display for PlacingError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -73,9 +93,18 @@ impl Display for PlacingError {
             PlacingError::OutOfBounds => f.write_str("Move is out of bound!"),
             PlacingError::Occupied => f.write_str("Move is occupied"),
             PlacingError::FullBoard => f.write_str("Entire board is filled"),
+            PlacingError::Forbidden => f.write_str("Move is forbidden by the rules"),
         }
     }
 }
+impl std::error::Error for PlacingError {}
+
+/// Final result of a finished game
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Outcome {
+    Win(Player),
+    Draw,
+}
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 enum GameResult {
@@ -101,6 +130,15 @@ impl GameResult {
             Player::O => GameResult::OWins,
         }
     }
+    fn outcome(&self) -> Option<Outcome> {
+        match self {
+            GameResult::XWins => Some(Outcome::Win(Player::X)),
+            GameResult::OWins => Some(Outcome::Win(Player::O)),
+            GameResult::Draws => Some(Outcome::Draw),
+            GameResult::NotTerminated => None,
+            GameResult::NotCalculated => None,
+        }
+    }
     fn utility(&self, player: Player) -> Option<Util> {
         match self {
             GameResult::XWins => match player {
@@ -118,9 +156,21 @@ impl GameResult {
     }
 }
 
+/// A placed stone with the state it replaced, so that `undo` can put it back
+#[derive(Clone, Copy)]
+struct Placement {
+    m: Move,
+    player: Player,
+    stones_left: usize,
+    last_placement: Option<Move>,
+    bounds: [usize; 4],
+    default_bound: bool,
+    game_result: GameResult,
+}
+
 #[derive(Clone)]
-pub struct Board {
-    grid: Array2<BoardValue>,
+pub struct Board<G: Grid = ArrayGrid> {
+    grid: G,
     pub player: Player,
     last_placement: Option<Move>,
     min_x: usize,
@@ -131,13 +181,41 @@ pub struct Board {
     game_result: GameResult,
     x_forced: Option<Vec<Move>>,
     o_forced: Option<Vec<Move>>,
+    x_threes: KnownThrees,
+    o_threes: KnownThrees,
+    /// Empty points where each player would win straight away
+    x_wins: Vec<Move>,
+    o_wins: Vec<Move>,
     width: usize,
     height: usize,
+    rule: Rule,
+    win_length: usize,
+    /// Stones the player to move still has to place this turn
+    stones_left: usize,
+    stone_count: usize,
+    restriction: Option<Restriction>,
+    /// Zobrist hash of the stones, the player to move is added in `hash`
+    stones_hash: u64,
+    history: Vec<Placement>,
+    /// Shapes around every point for looking threats up, `None` to work them out instead
+    shapes: Option<Shapes>,
 }
 impl Board {
     pub fn new(width: usize, height: usize) -> Board {
+        Board::with_rule(width, height, Rule::default())
+    }
+
+    pub fn with_rule(width: usize, height: usize, rule: Rule) -> Board {
+        Board::with_grid(width, height, rule)
+    }
+}
+impl<G: Grid> Board<G> {
+    /// Empty board keeping its stones in a G, `new` and `with_rule` use an `ArrayGrid`
+    pub fn with_grid(width: usize, height: usize, rule: Rule) -> Board<G> {
+        let grid = G::new(width, height);
+        let shapes = Shapes::new(&grid, width, height, rule, rule.default_win_length());
         Board {
-            grid: Array::from_elem((width, height), BoardValue::Empty),
+            grid,
             player: Player::FIRST,
             last_placement: None,
             min_x: 0,
@@ -148,9 +226,203 @@ impl Board {
             game_result: GameResult::NotTerminated,
             x_forced: Some(Vec::new()),
             o_forced: Some(Vec::new()),
+            x_threes: KnownThrees::default(),
+            o_threes: KnownThrees::default(),
+            x_wins: Vec::new(),
+            o_wins: Vec::new(),
             width,
             height,
+            rule,
+            win_length: rule.default_win_length(),
+            stones_left: 1,
+            stone_count: 0,
+            restriction: None,
+            stones_hash: 0,
+            history: Vec::new(),
+            shapes,
+        }
+    }
+
+    /// Play k in a row instead of five, k goes from 4 to 7
+    pub fn with_win_length(mut self, k: usize) -> Self {
+        assert!(
+            WIN_LENGTHS.contains(&k),
+            "win length {k} isn't between {} and {}",
+            WIN_LENGTHS.start(),
+            WIN_LENGTHS.end()
+        );
+        assert!(
+            self.rule != Rule::Renju || k == Rule::Renju.default_win_length(),
+            "Renju is only played with five in a row"
+        );
+        assert!(
+            self.last_placement.is_none(),
+            "win length has to be set before any stone is placed"
+        );
+        self.win_length = k;
+        if self.shapes.is_some() {
+            self.shapes = Shapes::new(&self.grid, self.width, self.height, self.rule, k);
+        }
+        self
+    }
+
+    /// Work threats out along each line instead of looking them up, the slower way, for comparing
+    /// the two
+    pub fn without_lookup(mut self) -> Self {
+        self.shapes = None;
+        self
+    }
+
+    /// Same position with its stones kept in an H, e.g. to search it on a `BitGrid`
+    pub fn into_grid<H: Grid>(self) -> Board<H> {
+        let mut grid = H::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                grid.set([y, x], self.grid[[y, x]]);
+            }
+        }
+        Board {
+            grid,
+            player: self.player,
+            last_placement: self.last_placement,
+            min_x: self.min_x,
+            min_y: self.min_y,
+            max_x: self.max_x,
+            max_y: self.max_y,
+            default_bound: self.default_bound,
+            game_result: self.game_result,
+            x_forced: self.x_forced,
+            o_forced: self.o_forced,
+            x_threes: self.x_threes,
+            o_threes: self.o_threes,
+            x_wins: self.x_wins,
+            o_wins: self.o_wins,
+            width: self.width,
+            height: self.height,
+            rule: self.rule,
+            win_length: self.win_length,
+            stones_left: self.stones_left,
+            stone_count: self.stone_count,
+            restriction: self.restriction,
+            stones_hash: self.stones_hash,
+            history: self.history,
+            shapes: self.shapes,
+        }
+    }
+
+    /// Play under a Pro or Long-Pro opening restriction
+    pub fn with_restriction(mut self, restriction: Restriction) -> Self {
+        assert!(
+            self.last_placement.is_none(),
+            "restriction has to be set before any stone is placed"
+        );
+        self.restriction = Some(restriction);
+        self
+    }
+
+    pub fn restriction(&self) -> Option<Restriction> {
+        self.restriction
+    }
+
+    pub fn rule(&self) -> Rule {
+        self.rule
+    }
+
+    pub fn win_length(&self) -> usize {
+        self.win_length
+    }
+
+    /// Zobrist hash of the position, equal for boards with the same stones and player to move
+    /// however they were reached
+    pub fn hash(&self) -> u64 {
+        self.stones_hash ^ zobrist::turn(self.player)
+    }
+
+    /// Whether other has the same stones and player to move, which boards of the same `hash`
+    /// only have short of a collision
+    pub fn same_position(&self, other: &Board<G>) -> bool {
+        [self.width, self.height, self.stone_count, self.stones_left]
+            == [
+                other.width,
+                other.height,
+                other.stone_count,
+                other.stones_left,
+            ]
+            && self.player == other.player
+            && (0..self.height)
+                .all(|y| (0..self.width).all(|x| self.grid[[y, x]] == other.grid[[y, x]]))
+    }
+
+    /// Stones the player to move still has to place before the turn passes, only ever more than
+    /// one in Connect6
+    pub fn stones_left(&self) -> usize {
+        self.stones_left
+    }
+
+    /// Player who placed the last stone, the opponent of the player to move unless they are in the
+    /// middle of their turn
+    pub fn last_mover(&self) -> Player {
+        if self.last_placement.is_some() && self.stones_left < self.rule.stones_per_turn() {
+            self.player
+        } else {
+            self.player.opponent()
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Value at `m`, `None` if `m` is off the board
+    pub fn get(&self, m: Move) -> Option<BoardValue> {
+        let [x, y] = m;
+        if x >= self.width || y >= self.height {
+            return None;
         }
+        Some(self.grid[[y, x]])
+    }
+
+    pub fn last_move(&self) -> Option<Move> {
+        self.last_placement
+    }
+
+    /// Drawing of the board for the terminal, `to_string` writes the compact notation instead
+    pub fn diagram(&self) -> Diagram<'_, G> {
+        Diagram(self)
+    }
+
+    /// Moves placed so far, oldest first
+    pub fn history(&self) -> impl Iterator<Item = Move> + '_ {
+        self.history.iter().map(|placement| placement.m)
+    }
+
+    /// Take back the last move placed, returning it, or `None` if there is none
+    pub fn undo(&mut self) -> Option<Move> {
+        let placement = self.history.pop()?;
+        let m @ [x, y] = placement.m;
+        let stone = self.grid[[y, x]];
+        self.stones_hash ^= zobrist::stone(m, stone);
+        self.grid.set([y, x], BoardValue::Empty);
+        if let Some(shapes) = &mut self.shapes {
+            shapes.update(m, stone, BoardValue::Empty);
+        }
+        self.x_forced = None;
+        self.o_forced = None;
+        self.update_threes(m);
+        self.update_win_points(m);
+        self.stone_count -= 1;
+
+        self.player = placement.player;
+        self.stones_left = placement.stones_left;
+        self.last_placement = placement.last_placement;
+        [self.min_x, self.min_y, self.max_x, self.max_y] = placement.bounds;
+        self.default_bound = placement.default_bound;
+        self.game_result = placement.game_result;
+        Some(m)
     }
 
     pub fn place(&mut self, m: Move) -> Result<(), PlacingError> {
@@ -161,22 +433,37 @@ impl Board {
         if self.grid[[y, x]] != BoardValue::Empty {
             return Err(PlacingError::Occupied);
         }
+        if self.is_forbidden(m) {
+            return Err(PlacingError::Forbidden);
+        }
 
         // println!("Moving {} {}", x, y);
-        self.grid[[y, x]] = self.player.board_value();
-        match (self.player, &self.x_forced, &self.o_forced) {
-            (Player::X, Some(fa), _) if fa.is_empty() || fa.contains(&m) => {
-                self.x_forced = None;
-                self.o_forced = None;
-            }
-            (Player::O, _, Some(fa)) if fa.is_empty() || fa.contains(&m) => {
-                self.x_forced = None;
-                self.o_forced = None;
-            }
-            _ => {}
+        self.history.push(Placement {
+            m,
+            player: self.player,
+            stones_left: self.stones_left,
+            last_placement: self.last_placement,
+            bounds: [self.min_x, self.min_y, self.max_x, self.max_y],
+            default_bound: self.default_bound,
+            game_result: self.game_result,
+        });
+        self.grid.set([y, x], self.player.board_value());
+        if let Some(shapes) = &mut self.shapes {
+            shapes.update(m, BoardValue::Empty, self.player.board_value());
         }
+        // forced moves may not have been followed, work them out again
+        self.x_forced = None;
+        self.o_forced = None;
+        self.update_threes(m);
+        self.update_win_points(m);
 
-        self.player.next();
+        self.stone_count += 1;
+        self.stones_hash ^= zobrist::stone(m, self.player.board_value());
+        self.stones_left -= 1;
+        if self.stones_left == 0 {
+            self.player.next();
+            self.stones_left = self.rule.stones_per_turn();
+        }
         self.last_placement = Some(m);
         self.update_bounds();
 
@@ -212,21 +499,14 @@ impl Board {
             return;
         }
 
-        let mut all_empty = true;
-        set_bounds!(usize::MAX, usize::MAX, usize::MIN, usize::MIN);
-        for x in 0..self.width {
-            for y in 0..self.height {
-                if self.grid[[y, x]] == BoardValue::Empty {
-                    continue;
-                }
-                all_empty = false;
-                set_bounds!(x, y);
+        match self.grid.bounds() {
+            Some([min_x, min_y, max_x, max_y]) => {
+                set_bounds!(min_x, min_y, max_x, max_y);
+                self.default_bound = false;
+            }
+            None => {
+                set_bounds!(0, 0, self.width - 1, self.height - 1);
             }
-        }
-        if all_empty {
-            set_bounds!(0, 0, self.width - 1, self.height - 1);
-        } else {
-            self.default_bound = false;
         }
     }
 
@@ -234,6 +514,11 @@ impl Board {
         self.calculate_game_result().utility(player)
     }
 
+    /// Outcome of the game, `None` while it is still going
+    pub fn result(&mut self) -> Option<Outcome> {
+        self.calculate_game_result().outcome()
+    }
+
     fn calculate_game_result(&mut self) -> GameResult {
         macro_rules! set_and_return {
             ($res:expr) => {
@@ -249,13 +534,37 @@ impl Board {
         if self.is_board_full() {
             set_and_return!(GameResult::Draws);
         }
+        let k = self.win_length;
         if let Some([x, y]) = self.last_placement {
-            if self.check_all_dir(x, y) {
-                set_and_return!(GameResult::win(self.grid[[y, x]].player().unwrap()));
+            let v = self.grid[[y, x]];
+            // a new win goes through the last stone
+            let win = self.grid.may_have_line([y, x], k)
+                && match self.grid.lines(v, k) {
+                    Some(lines) => lines
+                        .into_iter()
+                        .any(|([y, x], d)| self.check_line([x, y], d)),
+                    None => self.check_all_dir(x, y),
+                };
+            if win {
+                set_and_return!(GameResult::win(v.player().unwrap()));
             }
             set_and_return!(GameResult::NotTerminated);
         }
 
+        if let (Some(x_lines), Some(o_lines)) = (
+            self.grid.lines(BoardValue::X, k),
+            self.grid.lines(BoardValue::O, k),
+        ) {
+            for (player, lines) in [(Player::X, x_lines), (Player::O, o_lines)] {
+                if lines
+                    .into_iter()
+                    .any(|([y, x], d)| self.check_line([x, y], d))
+                {
+                    set_and_return!(GameResult::win(player));
+                }
+            }
+            set_and_return!(GameResult::NotTerminated);
+        }
         // may have lots of redundant checks if no last_placement
         for x in 0..self.width {
             for y in 0..self.height {
@@ -306,7 +615,7 @@ impl Board {
         }
     }
     fn is_board_full(&self) -> bool {
-        self.grid.iter().all(|&v| v != BoardValue::Empty)
+        self.grid.is_full()
     }
 
     fn calculate_forced(&mut self) -> Vec<Move> {
@@ -326,102 +635,215 @@ impl Board {
             (Player::O, _, Some(fa)) => return fa.clone(),
             _ => {}
         }
-        let mut x_forced = Vec::new();
-        let mut o_forced = Vec::new();
+        // winning comes first, then stopping the opponent from winning, then the threes of the
+        // player to move before the opponent's
+        let mut x_forced = self.forced_by_wins(Player::X);
+        let mut o_forced = self.forced_by_wins(Player::O);
+        for target in [self.player, self.player.opponent()] {
+            if !x_forced.is_empty() || !o_forced.is_empty() {
+                break;
+            }
+            let threes = self.known_threes(target);
+            let defences = Defences::of(&threes);
+            let attack: Vec<Move> = threes.into_iter().flat_map(|three| three.attack).collect();
+            // answering every three at once if one stone can, otherwise any of them
+            let defence = match defences.all.is_empty() {
+                true => defences.any,
+                false => defences.all,
+            };
+            match target {
+                Player::X => {
+                    extend(&mut x_forced, attack);
+                    extend(&mut o_forced, defence);
+                }
+                Player::O => {
+                    extend(&mut x_forced, defence);
+                    extend(&mut o_forced, attack);
+                }
+            }
+        }
+        self.x_forced = Some(x_forced.clone());
+        self.o_forced = Some(o_forced.clone());
+        match self.player {
+            Player::X => x_forced,
+            Player::O => o_forced,
+        }
+    }
+    /// Whether player would win by playing the empty point m
+    fn wins_at(&mut self, m: Move, player: Player) -> bool {
+        let [x, y] = m;
+        if self.grid[[y, x]] != BoardValue::Empty {
+            return false;
+        }
+        self.grid.set([y, x], player.board_value());
+        let win = self.check_all_dir(x, y);
+        self.grid.set([y, x], BoardValue::Empty);
+        win
+    }
 
-        if let Some([x, y]) = self.last_placement {
-            for target_value in [BoardValue::X, BoardValue::O] {
-                for area in self.get_areas_from_point(x, y) {
-                    let (af, df) = patterns::get_forced(&area, &target_value);
-                    // println!("Area {:?} {:?} {:?}", area, af, df);
-                    match target_value.player().unwrap() {
-                        Player::X => {
-                            extend(&mut x_forced, af.clone());
-                            extend(&mut o_forced, df.clone());
-                        }
-                        Player::O => {
-                            extend(&mut x_forced, df.clone());
-                            extend(&mut o_forced, af.clone());
-                        }
-                    }
+    /// Points on the board at most radius away from m along the lines through m, m included
+    fn line_points(&self, m: Move, radius: usize) -> Vec<Move> {
+        let radius = radius as isize;
+        let mut points = vec![m];
+        for (dx, dy) in [(1, 0), (0, 1), (1, 1), (1, -1)] {
+            for t in (-radius..=radius).filter(|&t| t != 0) {
+                match (
+                    m[0].checked_add_signed(dx * t),
+                    m[1].checked_add_signed(dy * t),
+                ) {
+                    (Some(x), Some(y)) if x < self.width && y < self.height => points.push([x, y]),
+                    _ => continue,
                 }
             }
-            self.x_forced = Some(x_forced.clone());
-            self.o_forced = Some(o_forced.clone());
-            return match self.player {
-                Player::X => x_forced,
-                Player::O => o_forced,
+        }
+        points
+    }
+
+    /// Note the stone placed or taken back at m for the threes found before, only the lines
+    /// through m change unless forbidden points elsewhere do too
+    fn update_threes(&mut self, m: Move) {
+        self.x_threes
+            .changed(m, self.rule.has_forbidden_moves(Player::X));
+        self.o_threes
+            .changed(m, self.rule.has_forbidden_moves(Player::O));
+    }
+
+    /// Keep the winning points of both players up to date after a stone is placed at m, only the
+    /// points on the lines through m can start or stop winning
+    fn update_win_points(&mut self, m: Move) {
+        // a stone right past the end of a line can still block it or make it too long
+        let line_points = self.line_points(m, self.win_length);
+        for player in [Player::X, Player::O] {
+            let mut points = match player {
+                Player::X => std::mem::take(&mut self.x_wins),
+                Player::O => std::mem::take(&mut self.o_wins),
             };
+            points.retain(|&p| !line_points.contains(&p));
+            for &p in &line_points {
+                if self.wins_at(p, player) {
+                    points.push(p);
+                }
+            }
+            match player {
+                Player::X => self.x_wins = points,
+                Player::O => self.o_wins = points,
+            }
+        }
+    }
+
+    /// Moves player is forced into by the winning points, its own if it has any, otherwise the
+    /// ones stopping all of the opponent's
+    fn forced_by_wins(&mut self, player: Player) -> Vec<Move> {
+        let (own, threats) = match player {
+            Player::X => (&self.x_wins, &self.o_wins),
+            Player::O => (&self.o_wins, &self.x_wins),
+        };
+        if !own.is_empty() {
+            return own.clone();
         }
+        if threats.is_empty() {
+            return Vec::new();
+        }
+        let threats = threats.clone();
+        let defences = self.defences(player, &threats);
+        if defences.is_empty() {
+            // lost anyway
+            threats
+        } else {
+            defences
+        }
+    }
 
-        // lots of redundant checks if no last_placement
-        // for ((y, x), target_value) in self.grid.indexed_iter() {
-        //     for area in self.get_areas_from_point(x, y) {
-        //         if let Some((df, af)) = patterns::get_forced(&area, &target_value) {
-        //             if self.player == target_value.player().unwrap() {
-        //                 af
-        //             } else {
-        //                 df
-        //             }
-        //             self.forced_actions = Some(fa.clone());
-        //             return fa;
-        //         }
-        //     }
-        // }
-        self.x_forced = Some(Vec::new());
-        self.o_forced = Some(Vec::new());
-        Vec::new()
+    /// Empty points where a stone of player stops every one of the opponent's winning threats,
+    /// filling a threat or, under Caro, blocking the line it would make
+    fn defences(&mut self, player: Player, threats: &[Move]) -> Vec<Move> {
+        let opponent = player.opponent();
+        let mut res = Vec::new();
+        // stopping the first threat needs a stone on one of its lines
+        for q in self.line_points(threats[0], self.win_length) {
+            let [x, y] = q;
+            if self.grid[[y, x]] != BoardValue::Empty {
+                continue;
+            }
+            self.grid.set([y, x], player.board_value());
+            let stopped = threats
+                .iter()
+                .all(|&p| p == q || !self.wins_at(p, opponent));
+            self.grid.set([y, x], BoardValue::Empty);
+            if stopped {
+                res.push(q);
+            }
+        }
+        res
     }
+
     pub fn are_there_threats(&mut self) -> bool {
         // return false;
         self.calculate_forced();
         !self.x_forced.as_ref().unwrap().is_empty() && !self.o_forced.as_ref().unwrap().is_empty()
     }
 
-    fn get_areas_from_point(&self, x: usize, y: usize) -> [Vec<(BoardValue, usize, usize)>; 4] {
-        let x_vary = (x.saturating_sub(6))..(usize::min(x + 7, self.width));
-        let y_vary = (y.saturating_sub(6))..(usize::min(y + 7, self.height));
-        let x_const = iter::repeat(x);
-        let y_const = iter::repeat(y);
-
-        let board_value = |(x, y)| -> (BoardValue, usize, usize) { (self.grid[[y, x]], x, y) };
-
-        let horz_area = iter::zip(x_vary.clone(), y_const.clone())
-            .map(board_value)
-            .collect();
-        let vert_area = iter::zip(x_const.clone(), y_vary.clone())
-            .map(board_value)
-            .collect();
-        let mut diag_area1 = Vec::new();
-        for t in -5..=5 {
-            match (x.checked_add_signed(t), y.checked_add_signed(t)) {
-                (Some(rx), Some(ry)) if rx < self.width && ry < self.height => {
-                    diag_area1.push(board_value((rx, ry)));
-                }
-                _ => continue,
-            }
+    /// Whether the player to move isn't allowed to play m, either because of the opening
+    /// restriction or because it is one of Black's forbidden moves in Renju
+    pub fn is_forbidden(&self, m: Move) -> bool {
+        if self.is_restricted(m) {
+            return true;
+        }
+        if !self.rule.has_forbidden_moves(self.player) {
+            return false;
+        }
+        ForbiddenChecker::new(&self.grid, self.width, self.height).is_forbidden(m)
+    }
+
+    /// Whether the rule forbids player the empty point m, whoever is to move
+    fn is_forbidden_for(&self, m: Move, player: Player) -> bool {
+        self.rule.has_forbidden_moves(player)
+            && ForbiddenChecker::new(&self.grid, self.width, self.height).is_forbidden(m)
+    }
+
+    /// Whether the opening restriction rules m out for the next stone, which only matters for
+    /// the first player's first two stones
+    fn is_restricted(&self, m: Move) -> bool {
+        let Some(restriction) = self.restriction else {
+            return false;
+        };
+        let [cx, cy] = [self.width / 2, self.height / 2];
+        match self.stone_count {
+            0 => m != [cx, cy],
+            2 => usize::max(m[0].abs_diff(cx), m[1].abs_diff(cy)) < restriction.min_distance(),
+            _ => false,
+        }
+    }
+
+    fn remove_forbidden(&self, moves: &mut Vec<Move>) {
+        if self.restriction.is_some() {
+            moves.retain(|&m| !self.is_restricted(m));
+        }
+        if !self.rule.has_forbidden_moves(self.player) {
+            return;
         }
-        let mut diag_area2 = Vec::new();
-        for t in -5..=5 {
-            match (x.checked_add_signed(t), y.checked_add_signed(-t)) {
-                (Some(rx), Some(ry)) if rx < self.width && ry < self.height => {
-                    diag_area2.push(board_value((rx, ry)));
+        let mut checker = ForbiddenChecker::new(&self.grid, self.width, self.height);
+        moves.retain(|&m| !checker.is_forbidden(m));
+    }
+
+    /// Every empty point that may be played, unlike `actions` which only returns the moves worth
+    /// searching
+    pub fn legal_moves(&self) -> Vec<Move> {
+        let mut res = Vec::new();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if self.grid[[y, x]] == BoardValue::Empty {
+                    res.push([x, y]);
                 }
-                _ => continue,
             }
         }
-        // let diag_area1 = iter::zip(x_vary.clone(), y_vary.clone())
-        //     .map(board_value)
-        //     .collect();
-        // let diag_area2 = iter::zip(x_vary.clone(), y_vary.rev().clone())
-        //     .map(board_value)
-        //     .collect();
-
-        [horz_area, vert_area, diag_area1, diag_area2]
+        self.remove_forbidden(&mut res);
+        res
     }
 
     pub fn actions(&mut self) -> Vec<Move> {
-        let forced_actions = self.calculate_forced();
+        let mut forced_actions = self.calculate_forced();
+        self.remove_forbidden(&mut forced_actions);
         if !forced_actions.is_empty() {
             return forced_actions;
         }
@@ -437,6 +859,11 @@ impl Board {
                 }
             }
         }
+        self.remove_forbidden(&mut res);
+        if res.is_empty() {
+            // every point next to the stones is forbidden
+            return self.legal_moves();
+        }
         res
     }
 
@@ -496,165 +923,162 @@ impl Board {
             self.count_ray(x, y, second_ray, v, blocking_v);
         let count = first_ray_count + second_ray_count + 1;
         let blocked = first_ray_blocked && second_ray_blocked;
-        (count == 5 && !blocked) || count > 5
+        self.rule
+            .is_win(count, blocked, v.player().unwrap(), self.win_length)
     }
 
     fn check_all_dir(&self, x: usize, y: usize) -> bool {
-        macro_rules! return_if_true {
-            ($b:expr) => {
-                if $b {
-                    return true;
-                }
-            };
+        if !self.grid.may_have_line([y, x], self.win_length) {
+            return false;
         }
+        Direction::ALL
+            .into_iter()
+            .any(|direction| self.check_line([x, y], direction))
+    }
 
+    /// Whether the stone at m is part of a winning line along direction
+    fn check_line(&self, [x, y]: Move, direction: Direction) -> bool {
         let v = self.grid[[y, x]];
         let blocking_v = v.opposite().expect("grid at this position is empty");
 
-        let pos_ray = 1..=5;
-        let neg_ray = (-5..=-1).rev();
-
-        // horizontal
-        let horz_first_range = iter::zip(pos_ray.clone(), iter::repeat(0));
-        let horz_second_range = iter::zip(neg_ray.clone(), iter::repeat(0));
-        return_if_true!(self.check_dir(x, y, horz_first_range, horz_second_range, v, blocking_v,));
-
-        // vertical
-        let vert_first_range = iter::zip(iter::repeat(0), pos_ray.clone());
-        let vert_second_range = iter::zip(iter::repeat(0), neg_ray.clone());
-        return_if_true!(self.check_dir(x, y, vert_first_range, vert_second_range, v, blocking_v));
-
-        // diagonals
-        let diag1_first_range = iter::zip(pos_ray.clone(), pos_ray.clone());
-        let diag1_second_range = iter::zip(neg_ray.clone(), neg_ray.clone());
-        return_if_true!(self.check_dir(x, y, diag1_first_range, diag1_second_range, v, blocking_v));
-        let diag2_first_range = iter::zip(pos_ray.clone(), neg_ray.clone());
-        let diag2_second_range = iter::zip(neg_ray.clone(), pos_ray.clone());
-        return_if_true!(self.check_dir(x, y, diag2_first_range, diag2_second_range, v, blocking_v));
-
-        false
+        // rays as long as a win so overlines are seen
+        let k = self.win_length as isize;
+        let (dx, dy) = direction.step();
+        let first_ray = (1..=k).map(|t| (dx * t, dy * t));
+        let second_ray = (1..=k).map(|t| (-dx * t, -dy * t));
+        self.check_dir(x, y, first_ray, second_ray, v, blocking_v)
     }
 
     pub fn heuristic(&self, player: Player) -> Util {
-        const W1: usize = 0;
-        const W2: usize = 2;
-        const W3: usize = 3;
-        const W4: usize = 4;
-
-        let get_w = |s| match s {
-            1 => W1,
-            2 => W2,
-            3 => W3,
-            4 => W4,
-            _ => 0,
-        };
-
-        let mut x_h = 0;
-        let mut o_h = 0;
-        let mut current_x_straight = 0;
-        let mut current_o_straight = 0;
-
-        let mut update = |v| match v {
-            BoardValue::X => current_x_straight += 1,
-            BoardValue::O => current_o_straight += 1,
-            BoardValue::Empty => {
-                x_h += get_w(current_x_straight);
-                o_h += get_w(current_o_straight);
-                current_x_straight = 0;
-                current_o_straight = 0;
+        // straights are worth their length, from two stones up to one short of a win
+        let get_w = |s| {
+            if (2..self.win_length).contains(&s) {
+                s
+            } else {
+                0
             }
         };
-        // horizontal straights
-        for y in 0..self.height {
-            for x in 0..self.width {
-                update(self.grid[[y, x]]);
-            }
-        }
-        update(BoardValue::Empty);
-
-        // vertical straights
-        for x in 0..self.width {
-            for y in 0..self.height {
-                update(self.grid[[y, x]]);
-            }
-        }
-        update(BoardValue::Empty);
-
-        // diagonal 1
-        for k in 0..(self.width + self.height - 1) {
-            for x in 0..self.width {
-                if let Some(y) = k.checked_sub(x) {
-                    if y < self.height {
-                        update(self.grid[[y, x]]);
-                    }
-                }
-            }
-        }
-        update(BoardValue::Empty);
 
-        // diagonal 2
-        for k in 0..(self.width + self.height - 1) {
-            for x in (0..self.width).rev() {
-                if let Some(y) = k.checked_sub(self.width - x - 1) {
-                    if y < self.height {
-                        update(self.grid[[y, x]]);
+        let span = self.rule.span(self.win_length);
+        let straights = |v| self.grid.straights(v, self.win_length, span);
+        let (x_h, o_h) = match (straights(BoardValue::X), straights(BoardValue::O)) {
+            (Some(x_h), Some(o_h)) => (x_h, o_h),
+            _ => {
+                let mut x_h = 0;
+                let mut o_h = 0;
+                for line in self.lines() {
+                    for (h, opponent) in [(&mut x_h, BoardValue::O), (&mut o_h, BoardValue::X)] {
+                        // straights walled in too tightly to ever win are worth nothing
+                        for space in line.split(|&v| v == opponent) {
+                            if space.len() < span {
+                                continue;
+                            }
+                            for straight in space.split(|&v| v == BoardValue::Empty) {
+                                *h += get_w(straight.len());
+                            }
+                        }
                     }
                 }
+                (x_h, o_h)
             }
-        }
-        update(BoardValue::Empty);
+        };
 
         // println!("{} {}", x_h, o_h);
         let x_h = x_h as f32;
         let o_h = o_h as f32;
+        if x_h + o_h == 0.0 {
+            return 0.5;
+        }
         match player {
             Player::X => x_h / (x_h + o_h),
-            Player::O => o_h / (x_h + o_h)
+            Player::O => o_h / (x_h + o_h),
         }
     }
 }
 
-impl Display for Board {
+impl<G: Grid> Board<G> {
+    /// Every row, column and diagonal of the board
+    fn lines(&self) -> Vec<Vec<BoardValue>> {
+        let at = |x: usize, y: usize, dx: isize, dy: isize| -> Option<(usize, usize)> {
+            let x = x.checked_add_signed(dx)?;
+            let y = y.checked_add_signed(dy)?;
+            (x < self.width && y < self.height).then_some((x, y))
+        };
+
+        let mut lines = Vec::new();
+        for (dx, dy) in [(1, 0), (0, 1), (1, 1), (1, -1)] {
+            for y in 0..self.height {
+                for x in 0..self.width {
+                    // lines start where the previous point is off the board
+                    if at(x, y, -dx, -dy).is_some() {
+                        continue;
+                    }
+                    let mut line = vec![self.grid[[y, x]]];
+                    let mut point = at(x, y, dx, dy);
+                    while let Some((px, py)) = point {
+                        line.push(self.grid[[py, px]]);
+                        point = at(px, py, dx, dy);
+                    }
+                    lines.push(line);
+                }
+            }
+        }
+        lines
+    }
+}
+
+/// Board drawn for the terminal, the last move highlighted, see `Board::diagram`
+pub struct Diagram<'a, G: Grid>(&'a Board<G>);
+impl<G: Grid> Display for Diagram<'_, G> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let board = self.0;
         // top border
         write!(f, "    ")?;
-        for i in 0..self.width {
-            write!(f, " {:#}  ", radix(i + 1, self.width as u8 + 1))?;
+        for i in 0..board.width {
+            write!(f, " {:#}  ", radix(i + 1, board.width as u8 + 1))?;
         }
         writeln!(f)?;
 
-        writeln!(f, "   ┌{}┐", ("───┬").repeat(self.width - 1) + "───")?;
+        writeln!(f, "   ┌{}┐", ("───┬").repeat(board.width - 1) + "───")?;
 
         // rows
-        for y in 0..self.height {
-            write!(f, " {:#} │", radix(y + 1, self.height as u8 + 1))?;
-            for x in 0..self.width {
-                match self.last_placement {
+        for y in 0..board.height {
+            write!(f, " {:#} │", radix(y + 1, board.height as u8 + 1))?;
+            for x in 0..board.width {
+                match board.last_placement {
                     Some(m) if m == [x, y] => {
-                        write!(f, " \x1b[100m{}\x1b[0m │", self.grid[[y, x]])?
+                        write!(f, " \x1b[100m{}\x1b[0m │", board.grid[[y, x]])?
                     }
-                    _ => write!(f, " {} │", self.grid[[y, x]])?,
+                    _ => write!(f, " {} │", board.grid[[y, x]])?,
                 }
             }
             writeln!(f)?;
             // bottom border of this row (top border of next row)
-            if y != self.height - 1 {
-                writeln!(f, "   ├{}┤", ("───┼").repeat(self.width - 1) + "───")?;
+            if y != board.height - 1 {
+                writeln!(f, "   ├{}┤", ("───┼").repeat(board.width - 1) + "───")?;
             }
         }
 
         // bottom border
-        writeln!(f, "   └{}┘", ("───┴").repeat(self.width - 1) + "───")?;
+        writeln!(f, "   └{}┘", ("───┴").repeat(board.width - 1) + "───")?;
         Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::board::Player;
+    use crate::board::{BitGrid, Move, PlacingError, Player, Restriction, Rule};
 
     use super::Board;
 
+    fn board_from_moves(rule: Rule, moves: &[Move]) -> Board {
+        let mut board = Board::with_rule(19, 19, rule);
+        for &m in moves {
+            board.place(m).unwrap();
+        }
+        board
+    }
+
     #[test]
     fn util_test() {
         let mut board = Board::new(19, 19);
@@ -708,9 +1132,300 @@ mod tests {
         assert_eq!(board.utility(Player::X), None);
     }
 
-    // #[test]
-    // fn test_area() {
-    //     let board = Board::new(19, 19);
-    //     println!("{:?}", board.get_areas_from_point(10, 10));
-    // }
+    #[test]
+    fn test_rules_win() {
+        const CARO: Rule = Rule::Caro {
+            overline_wins: true,
+        };
+        const CARO_EXACT: Rule = Rule::Caro {
+            overline_wins: false,
+        };
+        // X X X X X X
+        let six = [
+            [0, 0],
+            [0, 10],
+            [1, 0],
+            [2, 10],
+            [2, 0],
+            [4, 10],
+            [4, 0],
+            [6, 10],
+            [5, 0],
+            [8, 10],
+            [3, 0],
+        ];
+        // O X X X X X O
+        let blocked = [
+            [1, 5],
+            [0, 5],
+            [2, 5],
+            [6, 5],
+            [3, 5],
+            [0, 10],
+            [4, 5],
+            [2, 10],
+            [5, 5],
+        ];
+        // X X X X X O, against the edge
+        let edge = [
+            [0, 5],
+            [5, 5],
+            [1, 5],
+            [0, 10],
+            [2, 5],
+            [2, 10],
+            [3, 5],
+            [4, 10],
+            [4, 5],
+        ];
+        for (rule, wins) in [
+            (Rule::Freestyle, [true, true, true]),
+            (Rule::Standard, [false, true, true]),
+            (CARO, [true, false, false]),
+            (CARO_EXACT, [false, false, false]),
+        ] {
+            for (moves, win) in [&six[..], &blocked, &edge].into_iter().zip(wins) {
+                let expected = if win { Some(1.0) } else { None };
+                let mut board = board_from_moves(rule, moves);
+                assert_eq!(board.utility(Player::X), expected, "{rule:?} {moves:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_forced_respects_rule() {
+        // X X X X . X, the gap makes six
+        let moves = [
+            [0, 0],
+            [0, 10],
+            [1, 0],
+            [2, 10],
+            [2, 0],
+            [4, 10],
+            [5, 0],
+            [6, 10],
+            [3, 0],
+        ];
+        let mut board = board_from_moves(Rule::Freestyle, &moves);
+        assert_eq!(board.actions(), vec![[4, 0]]);
+        let mut board = board_from_moves(Rule::Standard, &moves);
+        assert_ne!(board.actions(), vec![[4, 0]]);
+    }
+
+    #[test]
+    fn test_heuristic_respects_rule() {
+        // O X X X X . O only has room for a five blocked at both ends
+        let moves = [
+            [1, 0],
+            [0, 0],
+            [2, 0],
+            [6, 0],
+            [3, 0],
+            [10, 10],
+            [4, 0],
+            [12, 12],
+        ];
+        let board = board_from_moves(Rule::default(), &moves);
+        assert_eq!(board.heuristic(Player::X), 0.5);
+        let board = board_from_moves(Rule::Freestyle, &moves);
+        assert_eq!(board.heuristic(Player::X), 1.0);
+    }
+
+    #[test]
+    fn test_caro_defences() {
+        // O X X X X . . can also be stopped by making the five blocked at both ends
+        let moves = [[1, 2], [0, 2], [2, 2], [10, 10], [3, 2], [12, 12], [4, 2]];
+        let mut board = board_from_moves(Rule::default(), &moves);
+        assert_eq!(board.actions(), vec![[5, 2], [6, 2]]);
+        let mut board = board_from_moves(Rule::Freestyle, &moves);
+        assert_eq!(board.actions(), vec![[5, 2]]);
+    }
+
+    #[test]
+    fn test_win_points_blocked_far_away() {
+        // O . X X X X then O blocks the other end, five points from the gap
+        let moves = [[3, 2], [1, 2], [4, 2], [10, 10], [5, 2], [12, 12], [6, 2]];
+        for (rule, wins) in [(Rule::default(), vec![]), (Rule::Freestyle, vec![[2, 2]])] {
+            let mut board = board_from_moves(rule, &moves);
+            board.place([7, 2]).unwrap();
+            assert_eq!(board.x_wins, wins, "{rule:?}");
+        }
+    }
+
+    #[test]
+    fn test_win_length() {
+        // four in a row on a board wider than it is tall
+        let mut board = Board::with_rule(9, 5, Rule::Freestyle).with_win_length(4);
+        for m in [[5, 4], [8, 0], [6, 4], [8, 1], [7, 4]] {
+            board.place(m).unwrap();
+        }
+        assert_eq!(board.actions(), vec![[4, 4], [8, 4]]);
+        board.place([8, 4]).unwrap();
+        board.place([4, 4]).unwrap();
+        assert_eq!(board.utility(Player::X), Some(1.0));
+
+        // five isn't enough for six in a row, which has to be blocked at five
+        let mut board = Board::with_rule(19, 19, Rule::Freestyle).with_win_length(6);
+        for m in [
+            [3, 3],
+            [0, 0],
+            [4, 3],
+            [0, 2],
+            [5, 3],
+            [0, 4],
+            [6, 3],
+            [0, 6],
+            [7, 3],
+        ] {
+            board.place(m).unwrap();
+        }
+        assert_eq!(board.utility(Player::X), None);
+        assert_eq!(board.actions(), vec![[2, 3], [8, 3]]);
+    }
+
+    #[test]
+    fn test_heuristic_win_length() {
+        // O X X X . O has room for four in a row but not five
+        let moves = [[1, 3], [0, 3], [2, 3], [5, 3], [3, 3]];
+        let board = board_from_moves(Rule::Freestyle, &moves);
+        assert_eq!(board.heuristic(Player::X), 0.5);
+        let mut board = Board::with_rule(19, 19, Rule::Freestyle).with_win_length(4);
+        for m in moves {
+            board.place(m).unwrap();
+        }
+        assert_eq!(board.heuristic(Player::X), 1.0);
+    }
+
+    #[test]
+    fn test_connect6_turns() {
+        let mut board = Board::with_rule(19, 19, Rule::Connect6);
+        assert_eq!(board.win_length(), 6);
+        board.place([9, 9]).unwrap();
+        assert_eq!((board.player, board.stones_left()), (Player::O, 2));
+        board.place([0, 0]).unwrap();
+        assert_eq!((board.player, board.stones_left()), (Player::O, 1));
+        assert_eq!(board.last_mover(), Player::O);
+        board.place([0, 2]).unwrap();
+        assert_eq!((board.player, board.stones_left()), (Player::X, 2));
+        assert_eq!(board.last_mover(), Player::O);
+        for m in [[10, 9], [11, 9], [0, 4], [0, 6], [12, 9], [13, 9]] {
+            board.place(m).unwrap();
+        }
+        // five isn't enough
+        assert_eq!(board.utility(Player::X), None);
+        board.place([18, 0]).unwrap();
+        board.place([18, 2]).unwrap();
+        board.place([14, 9]).unwrap();
+        assert_eq!(board.utility(Player::X), Some(1.0));
+    }
+
+    #[test]
+    fn test_pro_restrictions() {
+        let mut board = Board::new(15, 15).with_restriction(Restriction::Pro);
+        assert_eq!(board.actions(), vec![[7, 7]]);
+        assert!(matches!(board.place([6, 6]), Err(PlacingError::Forbidden)));
+        board.place([7, 7]).unwrap();
+        board.place([7, 8]).unwrap();
+        assert!(matches!(board.place([9, 9]), Err(PlacingError::Forbidden)));
+        assert!(board
+            .actions()
+            .iter()
+            .all(|&[x, y]| x.abs_diff(7) >= 3 || y.abs_diff(7) >= 3));
+        let mut random = board.clone();
+        random.place_random().unwrap();
+        let [x, y] = random.last_move().unwrap();
+        assert!(x.abs_diff(7) >= 3 || y.abs_diff(7) >= 3);
+        board.place([10, 7]).unwrap();
+        // the restriction is over
+        board.place([8, 8]).unwrap();
+
+        let mut board = Board::new(15, 15).with_restriction(Restriction::LongPro);
+        board.place([7, 7]).unwrap();
+        board.place([7, 8]).unwrap();
+        assert!(matches!(board.place([10, 7]), Err(PlacingError::Forbidden)));
+        board.place([11, 7]).unwrap();
+    }
+
+    #[test]
+    fn test_bit_grid_board() {
+        // random games on both grids have to agree move for move
+        for rule in [
+            Rule::default(),
+            Rule::Standard,
+            Rule::Renju,
+            Rule::Freestyle,
+        ] {
+            let mut array = Board::with_rule(15, 15, rule);
+            let mut bits = Board::<BitGrid>::with_grid(15, 15, rule);
+            while array.utility(Player::X).is_none() {
+                fastrand::seed(array.stone_count as u64);
+                let actions = array.actions();
+                fastrand::seed(array.stone_count as u64);
+                assert_eq!(actions, bits.actions());
+                assert_eq!(array.legal_moves(), bits.legal_moves());
+                if actions.is_empty() {
+                    break;
+                }
+                let m = actions[fastrand::usize(..actions.len())];
+                array.place(m).unwrap();
+                bits.place(m).unwrap();
+                assert_eq!(array.utility(Player::X), bits.utility(Player::X));
+                assert_eq!(array.heuristic(Player::O), bits.heuristic(Player::O));
+            }
+        }
+    }
+
+    #[test]
+    fn test_undo() {
+        // state worth comparing after the moves are taken back again
+        fn state(board: &mut Board) -> impl PartialEq + std::fmt::Debug {
+            let player = board.player;
+            let mut wins = [
+                board.x_wins.clone(),
+                board.o_wins.clone(),
+                board.forced_by_wins(player),
+            ];
+            wins.iter_mut().for_each(|points| points.sort());
+            let bounds = [board.min_x, board.min_y, board.max_x, board.max_y];
+            (
+                board.hash(),
+                (player, board.stones_left(), board.last_move()),
+                (bounds, wins),
+                board.result(),
+            )
+        }
+        for rule in [Rule::default(), Rule::Renju, Rule::Connect6] {
+            let mut board = Board::with_rule(15, 15, rule);
+            let mut states = Vec::new();
+            // random games can also end with only forbidden points left
+            loop {
+                let before = state(&mut board);
+                if board.result().is_some() || board.place_random().is_err() {
+                    break;
+                }
+                states.push(before);
+            }
+            // placing after the end doesn't change the outcome, until it is taken back
+            let end = state(&mut board);
+            if let Some(&m) = board.legal_moves().first() {
+                board.place(m).unwrap();
+                assert_eq!(board.undo(), Some(m));
+                assert_eq!(state(&mut board), end);
+            }
+
+            assert_eq!(board.history().count(), states.len());
+            while let Some(expected) = states.pop() {
+                let m = board.history().last();
+                assert_eq!(board.undo(), m);
+                assert_eq!(state(&mut board), expected);
+            }
+            assert_eq!(board.undo(), None);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_renju_needs_five() {
+        Board::with_rule(15, 15, Rule::Renju).with_win_length(6);
+    }
 }
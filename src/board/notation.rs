@@ -0,0 +1,268 @@
+//! One line notation of a position, written by `to_string` and read by `parse`, like
+//! `4/1x2/1o2 x 1,1 renju`.
+//!
+//! The rows go from the top separated by `/`, `x` and `o` are stones and numbers count empty
+//! points, so the rows also give the size. Then come the player to move and, if known, the last
+//! move as `x,y`. The rule and the win length follow only when they aren't the defaults, and the
+//! opening restriction, `pro` or `long-pro`, only when there is one.
+
+use std::{fmt::Display, str::FromStr};
+
+use super::{
+    lookup::Shapes, zobrist, Board, BoardValue, GameResult, Grid, Player, Restriction, Rule,
+    WIN_LENGTHS,
+};
+
+impl<G: Grid> Display for Board<G> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for y in 0..self.height {
+            if y > 0 {
+                f.write_str("/")?;
+            }
+            let mut empty = 0;
+            for x in 0..self.width {
+                let stone = match self.grid[[y, x]] {
+                    BoardValue::X => 'x',
+                    BoardValue::O => 'o',
+                    BoardValue::Empty => {
+                        empty += 1;
+                        continue;
+                    }
+                };
+                if empty > 0 {
+                    write!(f, "{empty}")?;
+                    empty = 0;
+                }
+                write!(f, "{stone}")?;
+            }
+            if empty > 0 {
+                write!(f, "{empty}")?;
+            }
+        }
+        match self.player {
+            Player::X => f.write_str(" x")?,
+            Player::O => f.write_str(" o")?,
+        }
+        if let Some([x, y]) = self.last_placement {
+            write!(f, " {x},{y}")?;
+        }
+        if self.rule != Rule::default() {
+            write!(f, " {}", self.rule)?;
+        }
+        if let Some(restriction) = self.restriction {
+            write!(f, " {restriction}")?;
+        }
+        if self.win_length != self.rule.default_win_length() {
+            write!(f, " {}", self.win_length)?;
+        }
+        Ok(())
+    }
+}
+
+/// Positions read this way have no history to undo
+impl<G: Grid> FromStr for Board<G> {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokens = s.split_whitespace();
+        let mut rows: Vec<Vec<BoardValue>> = Vec::new();
+        for row in tokens.next().ok_or("Position is empty")?.split('/') {
+            // the rows after the first can't be any longer
+            let max_len = rows.first().map_or(MAX_ROW_LEN, Vec::len);
+            rows.push(parse_row(row, max_len)?);
+        }
+        let (width, height) = (rows[0].len(), rows.len());
+        if width == 0 || rows.iter().any(|row| row.len() != width) {
+            return Err(String::from("Rows must have the same, non-zero, length"));
+        }
+        let player = match tokens.next() {
+            Some("x" | "X") => Player::X,
+            Some("o" | "O") => Player::O,
+            _ => return Err(String::from("Expected the player to move, x or o")),
+        };
+        let (mut last_move, mut rule, mut win_length) = (None, Rule::default(), None);
+        let mut restriction = None;
+        for token in tokens {
+            if let Some((x, y)) = token.split_once(',') {
+                let parse = |n: &str| n.parse().map_err(|_| format!("Bad move {token}"));
+                last_move = Some([parse(x)?, parse(y)?]);
+            } else if let Ok(k) = token.parse::<usize>() {
+                win_length = Some(k);
+            } else if let Ok(r) = token.parse::<Restriction>() {
+                restriction = Some(r);
+            } else {
+                rule = token.parse()?;
+            }
+        }
+        let win_length = win_length.unwrap_or(rule.default_win_length());
+        if !WIN_LENGTHS.contains(&win_length)
+            || (rule == Rule::Renju && win_length != Rule::Renju.default_win_length())
+        {
+            return Err(format!(
+                "Win length {win_length} can't be played under {rule}"
+            ));
+        }
+
+        let mut board = Board::<G>::with_grid(width, height, rule);
+        board.win_length = win_length;
+        board.restriction = restriction;
+        for (y, row) in rows.into_iter().enumerate() {
+            for (x, v) in row.into_iter().enumerate() {
+                if v != BoardValue::Empty {
+                    board.grid.set([y, x], v);
+                    board.stone_count += 1;
+                    board.stones_hash ^= zobrist::stone([x, y], v);
+                }
+            }
+        }
+        board.shapes = Shapes::new(&board.grid, width, height, rule, win_length);
+        board.player = player;
+        // in Connect6 the first turn has one stone and every other turn two
+        if board.stone_count.is_multiple_of(2) {
+            board.stones_left = 1;
+        } else {
+            board.stones_left = rule.stones_per_turn();
+        }
+        board.update_bounds();
+        for y in 0..height {
+            for x in 0..width {
+                for player in [Player::X, Player::O] {
+                    if board.wins_at([x, y], player) {
+                        match player {
+                            Player::X => board.x_wins.push([x, y]),
+                            Player::O => board.o_wins.push([x, y]),
+                        }
+                    }
+                }
+            }
+        }
+        // without a last move every line is checked for a win
+        board.game_result = GameResult::NotCalculated;
+        board.calculate_game_result();
+        board.x_forced = None;
+        board.o_forced = None;
+
+        if let Some(m) = last_move {
+            board.last_placement = Some(m);
+            if board.get(m) != Some(board.last_mover().board_value()) {
+                return Err(format!("No stone of the last mover at {m:?}"));
+            }
+        }
+        Ok(board)
+    }
+}
+
+/// Longest row read, far wider than any board played, so that a count of empty points can't ask
+/// for more memory than the position could use
+const MAX_ROW_LEN: usize = 1 << 10;
+
+/// Row of at most max_len points from stones and counts of empty points
+fn parse_row(row: &str, max_len: usize) -> Result<Vec<BoardValue>, String> {
+    let mut points = Vec::new();
+    let mut empty: usize = 0;
+    for c in row.chars() {
+        if let Some(digit) = c.to_digit(10) {
+            empty = empty
+                .checked_mul(10)
+                .and_then(|n| n.checked_add(digit as usize))
+                .filter(|&n| points.len() + n <= max_len)
+                .ok_or_else(|| format!("Row {row} is longer than {max_len} points"))?;
+            continue;
+        }
+        if points.len() + empty >= max_len {
+            return Err(format!("Row {row} is longer than {max_len} points"));
+        }
+        points.extend(std::iter::repeat_n(BoardValue::Empty, empty));
+        empty = 0;
+        match c {
+            'x' | 'X' => points.push(BoardValue::X),
+            'o' | 'O' => points.push(BoardValue::O),
+            _ => return Err(format!("Unexpected {c} in row {row}")),
+        }
+    }
+    points.extend(std::iter::repeat_n(BoardValue::Empty, empty));
+    Ok(points)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::board::{BitGrid, Board, Outcome, Player, Restriction, Rule};
+
+    #[test]
+    fn test_notation_round_trip() {
+        for rule in [Rule::default(), Rule::Renju, Rule::Connect6] {
+            let mut board = Board::with_rule(15, 13, rule);
+            while board.result().is_none() {
+                board.place_random().unwrap();
+                let text = board.to_string();
+                let mut read: Board = text.parse().unwrap();
+                assert_eq!(read.to_string(), text);
+                assert_eq!(read.hash(), board.hash());
+                assert_eq!(
+                    (read.last_mover(), read.stones_left()),
+                    (board.last_mover(), board.stones_left())
+                );
+                let (mut x_wins, mut o_wins) = (board.x_wins.clone(), board.o_wins.clone());
+                x_wins.sort();
+                o_wins.sort();
+                read.x_wins.sort();
+                read.o_wins.sort();
+                assert_eq!((&read.x_wins, &read.o_wins), (&x_wins, &o_wins));
+                assert_eq!(read.result(), board.result());
+            }
+        }
+    }
+
+    #[test]
+    fn test_notation() {
+        let mut board: Board = "5/1x3/1ox2/5 o 2,2".parse().unwrap();
+        assert_eq!((board.width(), board.height()), (5, 4));
+        assert_eq!(board.player, Player::O);
+        assert_eq!(board.last_move(), Some([2, 2]));
+        assert_eq!(board.history().count(), 0);
+        board.place([0, 0]).unwrap();
+        assert_eq!(board.to_string(), "o4/1x3/1ox2/5 x 0,0");
+
+        let board: Board = "9/9/9/9/9/9/9/9/9 x freestyle 4".parse().unwrap();
+        assert_eq!((board.rule(), board.win_length()), (Rule::Freestyle, 4));
+        assert_eq!(board.to_string(), "9/9/9/9/9/9/9/9/9 x freestyle 4");
+
+        // the first stone of a Pro game goes in the centre, the third far from it
+        let board: Board = "5/5/5/5/5 x pro".parse().unwrap();
+        assert_eq!(board.legal_moves(), vec![[2, 2]]);
+        assert_eq!(board.to_string(), "5/5/5/5/5 x pro");
+        let text = "9/9/9/9/4x4/4o4/9/9/9 x 4,5 caro-exact long-pro";
+        let mut board: Board = text.parse().unwrap();
+        assert_eq!(board.restriction(), Some(Restriction::LongPro));
+        assert_eq!(board.to_string(), text);
+        assert!(board.place([7, 7]).is_err());
+        board.place([8, 8]).unwrap();
+
+        // a five anywhere ends the game, not only through the last move
+        let mut board: Board<BitGrid> = "xxxxx2/7/ooooo2 o".parse().unwrap();
+        assert_eq!(board.result(), Some(Outcome::Win(Player::X)));
+        assert_eq!(board.to_string(), "xxxxx2/7/ooooo2 o");
+
+        for text in [
+            "",
+            "5/4 x",
+            "5/5",
+            "5/5 y",
+            "5/2z2 x",
+            "5/5 x 1,1",
+            "1x/2 x 1,0",
+            "5/5 x renju 6",
+            "5/5 x gomoku",
+            // too many empty points to count or to hold
+            "99999999999999999999x/5 x",
+            "100000000/5 x",
+            "5/99999999x x",
+            "5/4x1 x",
+        ] {
+            assert!(text.parse::<Board>().is_err(), "{text}");
+        }
+        // the longest row read
+        let board: Board = "1024/1024 x".parse().unwrap();
+        assert_eq!(board.width(), 1024);
+    }
+}
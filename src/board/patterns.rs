@@ -1,137 +1,869 @@
-use std::iter;
+//! Threats along a single line of the board, told apart by what one more stone does rather than
+//! by a list of patterns, so gaps, the edge of the board and the rule are all taken into account.
+//!
+//! A four has a point making five, an open four two or more the opponent can't all stop with
+//! one stone. A three has a point making an open four, and a closed three only a point making a
+//! four.
 
-use super::{BoardValue, Move};
+use super::{
+    lookup::Shapes, renju::ForbiddenChecker, Board, BoardValue, Grid, Move, Player, Rule,
+    WIN_LENGTHS,
+};
+
+/// Shape a stone makes with the player's other stones along one line, the strongest first
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Threat {
+    /// Wins, `XXXXX`
+    Five,
+    /// Makes five at two points and can't be stopped, `_XXXX_`
+    OpenFour,
+    /// Makes five at one point, `OXXXX_`, `X_XXX`, `XX_XX` and `XXX_X`
+    Four,
+    /// Solid three making an open four, `_XXX_`
+    OpenThree,
+    /// Three with a gap making an open four, `_X_XX_` and `_XX_X_`
+    SplitThree,
+    /// Three only making a four, `OXXX__` and `X_XX` at the edge
+    ClosedThree,
+}
 
-struct BoardPattern<const LEN: usize> {
-    targets: [bool; LEN],
-    def_forced: [bool; LEN],
-    atk_forced: [bool; LEN],
+/// Lines through a point
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Direction {
+    Horizontal,
+    Vertical,
+    Diagonal,
+    AntiDiagonal,
 }
-impl<const LEN: usize> BoardPattern<LEN> {
-    fn iter_forced(&self) -> impl Iterator<Item = (&bool, &bool)> {
-        iter::zip(self.atk_forced.iter(), self.def_forced.iter())
+impl Direction {
+    pub const ALL: [Direction; 4] = [
+        Direction::Horizontal,
+        Direction::Vertical,
+        Direction::Diagonal,
+        Direction::AntiDiagonal,
+    ];
+
+    /// Change of x and y from one point of the line to the next
+    pub fn step(&self) -> (isize, isize) {
+        match self {
+            Direction::Horizontal => (1, 0),
+            Direction::Vertical => (0, 1),
+            Direction::Diagonal => (1, 1),
+            Direction::AntiDiagonal => (1, -1),
+        }
     }
 }
 
-macro_rules! parse_bool_token {
-    (T) => {
-        true
-    };
-    (F) => {
-        false
-    };
+/// Three of a player along a line, found by `Board::threes`
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Three {
+    pub direction: Direction,
+    /// Points where the player makes it an open four
+    pub attack: Vec<Move>,
+    /// Points where a stone of the opponent leaves it no open four to make
+    pub defence: Vec<Move>,
 }
-macro_rules! bool_arr {
-    ($($tt:tt)*) => {
-        [$(parse_bool_token!($tt)),*]
-    };
+
+/// Points answering a player's threes together
+#[derive(Clone, PartialEq, Eq, Default, Debug)]
+pub struct Defences {
+    /// Points stopping every three, empty when no single stone does
+    pub all: Vec<Move>,
+    /// Points stopping at least one three
+    pub any: Vec<Move>,
+}
+impl Defences {
+    pub fn of(threes: &[Three]) -> Self {
+        let mut defences = Defences::default();
+        for (n, three) in threes.iter().enumerate() {
+            for &m in &three.defence {
+                if !defences.any.contains(&m) {
+                    defences.any.push(m);
+                }
+            }
+            match n {
+                0 => defences.all = three.defence.clone(),
+                _ => defences.all.retain(|m| three.defence.contains(m)),
+            }
+        }
+        defences
+    }
 }
 
-const THREE_PATTERN: BoardPattern<8> = BoardPattern {
-    targets: bool_arr!(F F T T T F F F),
-    def_forced: bool_arr!(T T F F F T T F),
-    atk_forced: bool_arr!(F F F F F T F F),
-};
-const FOUR_PATTERN1: BoardPattern<6> = BoardPattern {
-    targets: bool_arr!(F F T T T T),
-    def_forced: bool_arr!(T T F F F F),
-    atk_forced: bool_arr!(F T F F F F),
-};
-const FOUR_PATTERN2: BoardPattern<6> = BoardPattern {
-    targets: bool_arr!(F T F T T T),
-    def_forced: bool_arr!(T F T F F F),
-    atk_forced: bool_arr!(F F T F F F),
-};
-const FOUR_PATTERN3: BoardPattern<6> = BoardPattern {
-    targets: bool_arr!(F T T F T T),
-    def_forced: bool_arr!(T F F T F F),
-    atk_forced: bool_arr!(F F F T F F),
-};
-const FOUR_PATTERN4: BoardPattern<6> = BoardPattern {
-    targets: bool_arr!(F T T T F T),
-    def_forced: bool_arr!(T F F F T F),
-    atk_forced: bool_arr!(F F F F T F),
-};
-const FOUR_PATTERN5: BoardPattern<6> = BoardPattern {
-    targets: bool_arr!(F T T T T F),
-    def_forced: bool_arr!(T F F F F T),
-    atk_forced: bool_arr!(T F F F F T),
-};
+/// Threes of a player found before, and the points changed since, whose lines have to be looked
+/// at again
+#[derive(Clone, Default)]
+pub(super) struct KnownThrees {
+    threes: Option<Vec<Three>>,
+    changed: Vec<Move>,
+}
+impl KnownThrees {
+    /// Lines changed by more points than this are cheaper to scan all again
+    const MAX_CHANGED: usize = 8;
 
-pub fn get_forced(
-    area: &Vec<(BoardValue, usize, usize)>,
-    target_value: &BoardValue,
-) -> (Vec<Move>, Vec<Move>) {
-    let is_target = |(v, _x, _y): &(BoardValue, usize, usize)| -> bool { v == target_value };
-    let opposite = target_value.opposite().unwrap();
-    let isnt_opposite = |&(v, _x, _y): &(BoardValue, usize, usize)| -> bool { v != opposite };
-
-    let mut atk_forced = Vec::new();
-    let mut def_forced = Vec::new();
-
-    let target_iter: Vec<_> = area.iter().map(is_target).collect();
-    macro_rules! check_pat {
-        ($pat:ident, $i:ident) => {
-            let len = $pat.targets.len();
-            let area_window = &area[$i..usize::min($i + len, area.len())];
-            if area_window.iter().all(isnt_opposite) {
-                let window = &target_iter[$i..usize::min($i + len, area.len())];
-
-                if window.iter().eq($pat.targets.iter()) {
-                    for (&(_v, x, y), (&atk, &def)) in
-                        iter::zip(area_window.iter(), $pat.iter_forced())
-                    {
-                        if atk {
-                            atk_forced.push([x, y]);
-                        }
-                        if def {
-                            def_forced.push([x, y]);
-                        }
-                    }
-                    return (atk_forced, def_forced);
-                }
-                if window.iter().rev().eq($pat.targets.iter()) {
-                    for (&(_v, x, y), (&atk, &def)) in
-                        iter::zip(area_window.iter().rev(), $pat.iter_forced())
-                    {
-                        if atk {
-                            atk_forced.push([x, y]);
-                        }
-                        if def {
-                            def_forced.push([x, y]);
-                        }
-                    }
-                    return (atk_forced, def_forced);
+    /// Note a stone placed or taken back at m, forgetting everything if forbidden points
+    /// elsewhere may have changed too
+    pub(super) fn changed(&mut self, m: Move, forbidden: bool) {
+        if self.threes.is_none() {
+            return;
+        }
+        if forbidden || self.changed.len() == Self::MAX_CHANGED {
+            *self = KnownThrees::default();
+        } else {
+            self.changed.push(m);
+        }
+    }
+}
+
+/// Points of a line from edge to edge seen by one player, with the empty points the player may
+/// not play
+pub(super) struct Line {
+    cells: Vec<BoardValue>,
+    player: Player,
+    rule: Rule,
+    win_length: usize,
+    forbidden: Vec<bool>,
+}
+impl Line {
+    pub(super) fn new(
+        cells: Vec<BoardValue>,
+        player: Player,
+        rule: Rule,
+        win_length: usize,
+    ) -> Self {
+        Line {
+            forbidden: vec![false; cells.len()],
+            cells,
+            player,
+            rule,
+            win_length,
+        }
+    }
+
+    /// Threat made by the player's stone at i, and its key points, where the player makes
+    /// five from a four, an open four from an open or split three, or a four from a closed three
+    pub(super) fn threat(&mut self, i: usize) -> Option<(Threat, Vec<usize>)> {
+        if self.cells[i] != self.player.board_value() {
+            return None;
+        }
+        if self.is_five(i) {
+            return Some((Threat::Five, Vec::new()));
+        }
+        let fives = self.five_points(&[i]);
+        match fives.len() {
+            0 => {}
+            1 => return Some((Threat::Four, fives)),
+            _ if self.is_open(&fives, &[i]) => return Some((Threat::OpenFour, fives)),
+            _ => return Some((Threat::Four, fives)),
+        }
+        let k = self.win_length;
+        let (start, end) = self.run(i);
+        let is_solid = end - start >= k - 2;
+        let near = i.saturating_sub(k - 1)..usize::min(i + k, self.cells.len());
+        let (mut open_fours, mut fours) = (Vec::new(), Vec::new());
+        for q in near {
+            if !self.is_playable(q) {
+                continue;
+            }
+            self.cells[q] = self.player.board_value();
+            let fives = self.five_points(&[i, q]);
+            if self.is_open(&fives, &[i, q]) {
+                open_fours.push(q);
+            } else if !fives.is_empty() {
+                fours.push(q);
+            }
+            self.cells[q] = BoardValue::Empty;
+        }
+        match (open_fours.is_empty(), fours.is_empty()) {
+            (false, _) if is_solid => Some((Threat::OpenThree, open_fours)),
+            (false, _) => Some((Threat::SplitThree, open_fours)),
+            (true, false) => Some((Threat::ClosedThree, fours)),
+            (true, true) => None,
+        }
+    }
+
+    /// The player's threes on the line, each with its points making an open four and the
+    /// opponent's points leaving none. A line with a four is left to the board's winning points
+    pub(super) fn threes(&mut self) -> Vec<(Vec<usize>, Vec<usize>)> {
+        if !self.may_have_three() || self.has_four() {
+            return Vec::new();
+        }
+        let attack = (0..self.cells.len())
+            .filter(|&q| self.makes_open_four(q))
+            .collect();
+        let opponent = self.player.opponent().board_value();
+        group_threes(attack, self.cells.len(), self.win_length, |r, three| {
+            if self.cells[r] != BoardValue::Empty {
+                return false;
+            }
+            self.cells[r] = opponent;
+            let stopped = three.iter().all(|&q| !self.makes_open_four(q));
+            self.cells[r] = BoardValue::Empty;
+            stopped
+        })
+    }
+
+    /// Whether the player may play q and make an open four
+    fn makes_open_four(&mut self, q: usize) -> bool {
+        if !self.is_playable(q) || !self.has_room_at(q, self.win_length - 2) {
+            return false;
+        }
+        self.cells[q] = self.player.board_value();
+        let fives = self.five_points(&[q]);
+        let is_open = self.is_open(&fives, &[q]);
+        self.cells[q] = BoardValue::Empty;
+        is_open
+    }
+
+    /// Whether some stretch of win_length points has win_length - 2 of the player's stones and
+    /// none of the opponent's, which a three needs
+    pub(super) fn may_have_three(&self) -> bool {
+        (0..self.cells.len()).any(|i| self.has_room(i, self.win_length - 2))
+    }
+
+    /// Whether some stretch of win_length points starting at start has at least stones of the
+    /// player's stones and none of the opponent's
+    fn has_room(&self, start: usize, stones: usize) -> bool {
+        let Some(window) = self.cells.get(start..start + self.win_length) else {
+            return false;
+        };
+        let opponent = self.player.opponent().board_value();
+        let v = self.player.board_value();
+        !window.contains(&opponent) && window.iter().filter(|&&c| c == v).count() >= stones
+    }
+
+    /// Whether i is in a stretch that `has_room` for stones
+    fn has_room_at(&self, i: usize, stones: usize) -> bool {
+        (i.saturating_sub(self.win_length - 1)..=i).any(|start| self.has_room(start, stones))
+    }
+
+    fn is_playable(&self, q: usize) -> bool {
+        self.cells[q] == BoardValue::Empty && !self.forbidden[q]
+    }
+
+    /// Start and end, past the last stone, of the run of the player's stones through i
+    fn run(&self, i: usize) -> (usize, usize) {
+        let v = self.player.board_value();
+        let before = self.cells[..i]
+            .iter()
+            .rev()
+            .take_while(|&&c| c == v)
+            .count();
+        let after = self.cells[i + 1..].iter().take_while(|&&c| c == v).count();
+        (i - before, i + 1 + after)
+    }
+
+    /// Whether the player's stone at i is part of a winning line
+    fn is_five(&self, i: usize) -> bool {
+        let (start, end) = self.run(i);
+        let is_closed = |p: Option<usize>| {
+            p.and_then(|p| self.cells.get(p))
+                .is_none_or(|&c| c == self.player.opponent().board_value())
+        };
+        let blocked = is_closed(start.checked_sub(1)) && is_closed(Some(end));
+        self.rule
+            .is_win(end - start, blocked, self.player, self.win_length)
+    }
+
+    /// Whether the player makes five somewhere on the line with one more stone
+    fn has_four(&mut self) -> bool {
+        (0..self.cells.len()).any(|p| self.makes_five(p, &[]))
+    }
+
+    /// Empty points where the player makes five with stones
+    fn five_points(&mut self, stones: &[usize]) -> Vec<usize> {
+        let k = self.win_length;
+        let near = stones[0].saturating_sub(k)..usize::min(stones[0] + k + 1, self.cells.len());
+        near.filter(|&p| self.makes_five(p, stones)).collect()
+    }
+
+    /// Whether the player makes five with stones by playing the empty point p
+    fn makes_five(&mut self, p: usize, stones: &[usize]) -> bool {
+        // a five has the stone at p and win_length - 1 more in a row
+        if self.cells[p] != BoardValue::Empty || !self.has_room_at(p, self.win_length - 1) {
+            return false;
+        }
+        self.cells[p] = self.player.board_value();
+        let (start, end) = self.run(p);
+        let wins = stones.iter().all(|i| (start..end).contains(i)) && self.is_five(p);
+        self.cells[p] = BoardValue::Empty;
+        wins
+    }
+
+    /// Whether no single stone of the opponent stops all of fives made with stones
+    fn is_open(&mut self, fives: &[usize], stones: &[usize]) -> bool {
+        if fives.len() < 2 {
+            return false;
+        }
+        let opponent = self.player.opponent().board_value();
+        (0..self.cells.len()).all(|r| {
+            // a stone further away than win_length from a five point can't stop it
+            let is_near = fives.iter().any(|&p| p.abs_diff(r) <= self.win_length);
+            if self.cells[r] != BoardValue::Empty || !is_near {
+                return true;
+            }
+            self.cells[r] = opponent;
+            let stopped = self.five_points(stones).is_empty();
+            self.cells[r] = BoardValue::Empty;
+            !stopped
+        })
+    }
+}
+
+impl Three {
+    fn line_index(&self) -> isize {
+        line_index(self.attack[0], self.direction)
+    }
+}
+
+/// Number telling apart the lines along direction, the same for every point of a line and going
+/// up from one line to the next
+fn line_index([x, y]: Move, direction: Direction) -> isize {
+    let [x, y] = [x as isize, y as isize];
+    match direction {
+        Direction::Horizontal => y,
+        Direction::Vertical => x,
+        // x - y is the same along a diagonal and x + y along an anti-diagonal
+        Direction::Diagonal => x - y,
+        Direction::AntiDiagonal => x + y,
+    }
+}
+
+/// Threes along a line of len points from the points making open fours on it, each with the
+/// points where stops tells a stone of the opponent leaves none of its points
+pub(super) fn group_threes(
+    attack: Vec<usize>,
+    len: usize,
+    win_length: usize,
+    mut stops: impl FnMut(usize, &[usize]) -> bool,
+) -> Vec<(Vec<usize>, Vec<usize>)> {
+    // the points making open fours out of one three are closer than win_length
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    for q in attack {
+        match groups.last_mut() {
+            Some(group) if q - group[group.len() - 1] < win_length => group.push(q),
+            _ => groups.push(vec![q]),
+        }
+    }
+    groups
+        .into_iter()
+        .map(|three| {
+            // a stone further away can't stop an open four made at these points
+            let end = usize::min(three[three.len() - 1] + win_length + 1, len);
+            let near = three[0].saturating_sub(win_length)..end;
+            let defence = near.filter(|&r| stops(r, &three)).collect();
+            (three, defence)
+        })
+        .collect()
+}
+
+impl<G: Grid> Board<G> {
+    /// Threat the stone at m makes along direction for whoever's stone it is, `None` for an
+    /// empty point or a stone making none
+    pub fn threat(&self, m: Move, direction: Direction) -> Option<Threat> {
+        self.threat_with_points(m, direction)
+            .map(|(threat, _)| threat)
+    }
+
+    /// Key points of the threat the stone at m makes along direction, where its player makes
+    /// five from a four, an open four from an open or split three, or a four from a closed three
+    pub fn threat_points(&self, m: Move, direction: Direction) -> Vec<Move> {
+        self.threat_with_points(m, direction)
+            .map(|(_, points)| points)
+            .unwrap_or_default()
+    }
+
+    fn threat_with_points(&self, m: Move, direction: Direction) -> Option<(Threat, Vec<Move>)> {
+        let player = self.get(m)?.player()?;
+        // the tables can't tell forbidden points
+        if let Some(shapes) = &self.shapes {
+            if !self.rule.has_forbidden_moves(player) {
+                let threat = shapes.threat(&self.grid, m, direction, player)?;
+                let points = shapes.key_points(&self.grid, m, direction, player);
+                return Some((threat, points));
+            }
+        }
+        let points = self.line_points_through(m, direction);
+        let i = points.iter().position(|&p| p == m).unwrap();
+        let (threat, key_points) = self.line(&points, player).threat(i)?;
+        Some((threat, key_points.into_iter().map(|q| points[q]).collect()))
+    }
+
+    /// Every three of player on the board, lines with a four are left to the winning points
+    pub fn threes(&self, player: Player) -> Vec<Three> {
+        if self.stone_count == 0 {
+            return Vec::new();
+        }
+        let mut threes = Vec::new();
+        for direction in Direction::ALL {
+            for m in self.line_starts(direction) {
+                threes.extend(self.line_threes(m, direction, player));
+            }
+        }
+        threes
+    }
+
+    /// `threes` of player, in the same order, scanning again only the lines through the points
+    /// changed since they were last found
+    pub(super) fn known_threes(&mut self, player: Player) -> Vec<Three> {
+        let known = match player {
+            Player::X => &mut self.x_threes,
+            Player::O => &mut self.o_threes,
+        };
+        let KnownThrees {
+            threes: Some(mut threes),
+            changed,
+        } = std::mem::take(known)
+        else {
+            let threes = self.threes(player);
+            self.set_known_threes(player, threes.clone());
+            return threes;
+        };
+        let mut lines: Vec<(Direction, isize)> = Vec::new();
+        for m in changed {
+            for direction in Direction::ALL {
+                let line = (direction, line_index(m, direction));
+                if !lines.contains(&line) {
+                    lines.push(line);
                 }
             }
+        }
+        threes.retain(|three| !lines.contains(&(three.direction, three.line_index())));
+        for &(direction, index) in &lines {
+            if let Some(start) = self.line_start(direction, index) {
+                threes.extend(self.line_threes(start, direction, player));
+            }
+        }
+        // lines in the order `threes` goes through them, a line's own threes stay in order
+        threes.sort_by_key(|three| (three.direction as usize, three.line_index()));
+        self.set_known_threes(player, threes.clone());
+        threes
+    }
+
+    fn set_known_threes(&mut self, player: Player, threes: Vec<Three>) {
+        let known = KnownThrees {
+            threes: Some(threes),
+            changed: Vec::new(),
+        };
+        match player {
+            Player::X => self.x_threes = known,
+            Player::O => self.o_threes = known,
+        }
+    }
+
+    /// Threes of player on the line from the edge at start along direction
+    fn line_threes(&self, start: Move, direction: Direction, player: Player) -> Vec<Three> {
+        if !self.may_have_three(start, direction, player) {
+            return Vec::new();
+        }
+        let points = self.line_points_through(start, direction);
+        let found = match &self.shapes {
+            Some(shapes) => self.looked_up_threes(shapes, &points, direction, player),
+            None => self.line(&points, player).threes(),
+        };
+        found
+            .into_iter()
+            .map(|(attack, defence)| Three {
+                direction,
+                attack: attack.into_iter().map(|i| points[i]).collect(),
+                defence: defence.into_iter().map(|i| points[i]).collect(),
+            })
+            .collect()
+    }
+
+    /// The point at the edge starting the line along direction with `line_index` index, if the
+    /// line is on the board
+    fn line_start(&self, direction: Direction, index: isize) -> Option<Move> {
+        let (w, h) = (self.width as isize, self.height as isize);
+        let [x, y] = match direction {
+            Direction::Horizontal => [0, index],
+            Direction::Vertical => [index, 0],
+            Direction::Diagonal => [index.max(0), (-index).max(0)],
+            Direction::AntiDiagonal => [(index - (h - 1)).max(0), index.min(h - 1)],
+        };
+        ((0..w).contains(&x) && (0..h).contains(&y)).then_some([x as usize, y as usize])
+    }
+
+    /// `Line::threes` of the line of points along direction from the shapes around them
+    fn looked_up_threes(
+        &self,
+        shapes: &Shapes,
+        points: &[Move],
+        direction: Direction,
+        player: Player,
+    ) -> Vec<(Vec<usize>, Vec<usize>)> {
+        let is_empty = |i: usize| self.get(points[i]) == Some(BoardValue::Empty);
+        let mut attack = Vec::new();
+        for i in (0..points.len()).filter(|&i| is_empty(i)) {
+            match shapes.threat(&self.grid, points[i], direction, player) {
+                Some(Threat::Five) => return Vec::new(),
+                Some(Threat::OpenFour) => attack.push(i),
+                _ => {}
+            }
+        }
+        if self.rule.has_forbidden_moves(player) && !attack.is_empty() {
+            let mut checker = ForbiddenChecker::new(&self.grid, self.width, self.height);
+            attack.retain(|&i| !checker.is_forbidden(points[i]));
+        }
+        let k = self.win_length;
+        group_threes(attack, points.len(), k, |r, three| {
+            is_empty(r)
+                && three.iter().all(|&q| {
+                    let t = r as isize - q as isize;
+                    q == r
+                        || (q.abs_diff(r) <= k
+                            && shapes.threat_closed(
+                                &self.grid,
+                                points[q],
+                                direction,
+                                player,
+                                Some(t),
+                            ) != Some(Threat::OpenFour))
+                })
+        })
+    }
+
+    /// `Line::may_have_three` for the line from the edge at start along direction, without
+    /// gathering its points
+    fn may_have_three(&self, start: Move, direction: Direction, player: Player) -> bool {
+        let (dx, dy) = direction.step();
+        let k = self.win_length;
+        // the player's stones among the last k points, and the points since the opponent's last
+        let mut window = [false; *WIN_LENGTHS.end()];
+        let (mut stones, mut open) = (0, 0);
+        let [mut x, mut y] = start;
+        for t in 0.. {
+            let v = self.grid[[y, x]];
+            stones -= window[t % k] as usize;
+            window[t % k] = v == player.board_value();
+            stones += window[t % k] as usize;
+            open = match v == player.opponent().board_value() {
+                true => 0,
+                false => open + 1,
+            };
+            if open >= k && stones >= k - 2 {
+                return true;
+            }
+            match (x.checked_add_signed(dx), y.checked_add_signed(dy)) {
+                (Some(nx), Some(ny)) if nx < self.width && ny < self.height => [x, y] = [nx, ny],
+                _ => return false,
+            }
+        }
+        false
+    }
+
+    /// The point at the edge starting each line along direction through the stones' bounds, in
+    /// the order of `line_index`
+    fn line_starts(&self, direction: Direction) -> Vec<Move> {
+        let (w, h) = (self.width as isize, self.height as isize);
+        let [min_x, min_y, max_x, max_y] =
+            [self.min_x, self.min_y, self.max_x, self.max_y].map(|n| n as isize);
+        let points: Vec<[isize; 2]> = match direction {
+            Direction::Horizontal => (min_y..=max_y).map(|y| [0, y]).collect(),
+            Direction::Vertical => (min_x..=max_x).map(|x| [x, 0]).collect(),
+            // x - y is the same along a diagonal and x + y along an anti-diagonal
+            Direction::Diagonal => (min_x - max_y..=max_x - min_y)
+                .map(|d| [d.max(0), (-d).max(0)])
+                .collect(),
+            Direction::AntiDiagonal => (min_x + min_y..=max_x + max_y)
+                .map(|s| [(s - (h - 1)).max(0), s.min(h - 1)])
+                .collect(),
         };
+        points
+            .into_iter()
+            .filter(|&[x, y]| x < w && y < h)
+            .map(|[x, y]| [x as usize, y as usize])
+            .collect()
     }
-    for i in 0..area.len() {
-        if fastrand::bool() {
-            check_pat!(THREE_PATTERN, i);
+
+    /// Points of the line through m along direction from edge to edge
+    fn line_points_through(&self, m: Move, direction: Direction) -> Vec<Move> {
+        let (dx, dy) = direction.step();
+        let at = |t: isize| {
+            let x = m[0].checked_add_signed(dx * t)?;
+            let y = m[1].checked_add_signed(dy * t)?;
+            (x < self.width && y < self.height).then_some([x, y])
+        };
+        let start = (1..).take_while(|&t| at(-t).is_some()).count() as isize;
+        (-start..).map_while(at).collect()
+    }
+
+    /// Line of points seen by player, its forbidden points are only worked out when it may hold
+    /// a three
+    fn line(&self, points: &[Move], player: Player) -> Line {
+        let cells = points.iter().map(|&[x, y]| self.grid[[y, x]]).collect();
+        let mut line = Line::new(cells, player, self.rule, self.win_length);
+        if self.rule.has_forbidden_moves(player) && line.may_have_three() {
+            let mut checker = ForbiddenChecker::new(&self.grid, self.width, self.height);
+            for (i, &p) in points.iter().enumerate() {
+                line.forbidden[i] =
+                    self.get(p) == Some(BoardValue::Empty) && checker.is_forbidden(p);
+            }
         }
-        check_pat!(FOUR_PATTERN1, i);
-        // check_pat!(FOUR_PATTERN2, i);
-        // check_pat!(FOUR_PATTERN3, i);
-        // check_pat!(FOUR_PATTERN4, i);
-        check_pat!(FOUR_PATTERN5, i);
+        line
     }
-    (Vec::new(), Vec::new())
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::board::Board;
+    use super::{Defences, Direction, Threat, Three};
+    use crate::board::{Board, Move, Player, Rule};
+
+    const SIZE: usize = 15;
+
+    /// Board with shape laid along direction from start, `x` and `o` stones and `_` empty
+    fn shape_board(shape: &str, start: Move, direction: Direction, rule: &str) -> Board {
+        let mut rows = vec![vec!['_'; SIZE]; SIZE];
+        let (dx, dy) = direction.step();
+        for (t, c) in shape.chars().enumerate() {
+            let x = start[0].checked_add_signed(dx * t as isize).unwrap();
+            let y = start[1].checked_add_signed(dy * t as isize).unwrap();
+            rows[y][x] = c;
+        }
+        let rows: Vec<String> = rows
+            .iter()
+            .map(|row| {
+                let mut text = String::new();
+                let mut empty = 0;
+                for &c in row {
+                    if c == '_' {
+                        empty += 1;
+                        continue;
+                    }
+                    if empty > 0 {
+                        text += &empty.to_string();
+                        empty = 0;
+                    }
+                    text.push(c);
+                }
+                if empty > 0 {
+                    text += &empty.to_string();
+                }
+                text
+            })
+            .collect();
+        format!("{} o {rule}", rows.join("/")).parse().unwrap()
+    }
+
+    /// Checks the threat of every `x` of shape laid in the middle of the board and from the edge
+    /// in each direction
+    fn check_shape(shape: &str, middle: Option<Threat>, edge: Option<Threat>, rule: &str) {
+        let len = shape.len() as isize;
+        let mid = (SIZE / 2) as isize;
+        for direction in Direction::ALL {
+            let (dx, dy) = direction.step();
+            let last = SIZE - 1;
+            let edge_start = match direction {
+                Direction::AntiDiagonal => [0, last],
+                _ => [0, 0],
+            };
+            let middle_start = [
+                (mid - dx * (len / 2)) as usize,
+                (mid - dy * (len / 2)) as usize,
+            ];
+            for (start, expected) in [(middle_start, middle), (edge_start, edge)] {
+                let board = shape_board(shape, start, direction, rule);
+                for (t, c) in shape.chars().enumerate() {
+                    let x = start[0].checked_add_signed(dx * t as isize).unwrap();
+                    let y = start[1].checked_add_signed(dy * t as isize).unwrap();
+                    if c == 'x' {
+                        assert_eq!(
+                            board.threat([x, y], direction),
+                            expected,
+                            "{shape} {rule} {direction:?} from {start:?}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_fives_and_fours() {
+        check_shape("xxxxx", Some(Threat::Five), Some(Threat::Five), "");
+        check_shape("xxxx", Some(Threat::OpenFour), Some(Threat::Four), "");
+        check_shape("oxxxx", Some(Threat::Four), Some(Threat::Four), "");
+        for shape in ["x_xxx", "xx_xx", "xxx_x"] {
+            check_shape(shape, Some(Threat::Four), Some(Threat::Four), "");
+        }
+        // blocked at both ends only a five under Freestyle
+        check_shape("oxxxxxo", None, None, "");
+        check_shape(
+            "oxxxxxo",
+            Some(Threat::Five),
+            Some(Threat::Five),
+            "freestyle",
+        );
+        // the gap makes six, which Standard doesn't count
+        check_shape(
+            "oxxx_xxo",
+            Some(Threat::Four),
+            Some(Threat::Four),
+            "freestyle",
+        );
+        check_shape("oxxx_xxo", None, None, "standard");
+    }
+
+    #[test]
+    fn test_threes() {
+        check_shape(
+            "xxx",
+            Some(Threat::OpenThree),
+            Some(Threat::ClosedThree),
+            "",
+        );
+        // an open four next to the edge can be stopped under Caro by blocking its far end
+        check_shape(
+            "_xxx",
+            Some(Threat::OpenThree),
+            Some(Threat::ClosedThree),
+            "",
+        );
+        check_shape(
+            "_xxx",
+            Some(Threat::OpenThree),
+            Some(Threat::OpenThree),
+            "freestyle",
+        );
+        for shape in ["x_xx", "xx_x"] {
+            check_shape(
+                shape,
+                Some(Threat::SplitThree),
+                Some(Threat::ClosedThree),
+                "",
+            );
+            check_shape(
+                &format!("_{shape}"),
+                Some(Threat::SplitThree),
+                Some(Threat::SplitThree),
+                "freestyle",
+            );
+        }
+        // no room for a five at the edge
+        check_shape("xxx_o", Some(Threat::ClosedThree), None, "");
+        for shape in ["oxxx", "oxx_x", "ox_xx"] {
+            check_shape(
+                shape,
+                Some(Threat::ClosedThree),
+                Some(Threat::ClosedThree),
+                "",
+            );
+        }
+        check_shape("xx", None, None, "");
+        check_shape("oxxxo", None, None, "");
+    }
+
+    #[test]
+    fn test_threat_points() {
+        // the gap makes the open four, then only fours are left
+        let board = shape_board("_x_xx_", [4, 7], Direction::Vertical, "");
+        assert_eq!(
+            board.threat_points([4, 8], Direction::Vertical),
+            vec![[4, 9]]
+        );
+        assert!(board
+            .threat_points([4, 8], Direction::Horizontal)
+            .is_empty());
+        let board = shape_board("_x_xx_o", [4, 7], Direction::Vertical, "");
+        assert_eq!(
+            board.threat_points([4, 8], Direction::Vertical),
+            vec![[4, 7], [4, 9], [4, 12]]
+        );
+        let board = shape_board("oxxx_x", [4, 7], Direction::Horizontal, "");
+        assert_eq!(
+            board.threat_points([5, 7], Direction::Horizontal),
+            vec![[8, 7]]
+        );
+        let board = shape_board("oxxx__", [4, 7], Direction::Diagonal, "");
+        assert_eq!(
+            board.threat_points([5, 8], Direction::Diagonal),
+            vec![[8, 11], [9, 12]]
+        );
+    }
 
     #[test]
     fn test_forced() {
+        // the split three is answered in its gap or at either end, and under Caro also a point
+        // further out, which leaves its open four a five blocked at both ends
+        let board = shape_board("_x_xx_", [4, 7], Direction::Horizontal, "");
+        let threes = board.threes(Player::X);
+        assert_eq!(
+            threes,
+            vec![Three {
+                direction: Direction::Horizontal,
+                attack: vec![[6, 7]],
+                defence: vec![[3, 7], [4, 7], [6, 7], [9, 7], [10, 7]],
+            }]
+        );
+        assert!(board.threes(Player::O).is_empty());
+
+        // a three along the edge, which O can't stop from the end next to the edge
         let mut board = Board::new(19, 19);
         board.place([0, 3]).unwrap();
         board.place([1, 3]).unwrap();
         board.place([0, 4]).unwrap();
         board.place([2, 4]).unwrap();
         board.place([0, 5]).unwrap();
-        println!("{board}");
-        println!("{:?}", board.calculate_forced());
+        assert_eq!(board.actions(), vec![[0, 1], [0, 2], [0, 6], [0, 7]]);
+    }
+
+    #[test]
+    fn test_known_threes() {
+        // asking after a few moves at a time, sometimes too many to keep track of
+        let check = |board: &mut Board, n: usize| {
+            if n % 12 < 3 {
+                for player in [Player::X, Player::O] {
+                    assert_eq!(board.known_threes(player), board.threes(player));
+                }
+            }
+        };
+        for rule in [Rule::default(), Rule::Freestyle, Rule::Renju] {
+            for (game, lookup) in [true, false].repeat(5).into_iter().enumerate() {
+                let mut board = Board::with_rule(SIZE, SIZE, rule);
+                if !lookup {
+                    board = board.without_lookup();
+                }
+                let mut n = game;
+                while board.result().is_none() && board.place_random().is_ok() {
+                    n += 1;
+                    check(&mut board, n);
+                }
+                while board.undo().is_some() {
+                    n += 1;
+                    check(&mut board, n);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_defences() {
+        // the threes on the row and on the column are both stopped at 8,7, far from the last move
+        let text = "o13o/1o13/15/15/15/15/15/5xxx7/8x6/8x6/8x6/15/15/15/o13o o";
+        let mut board: Board = text.parse().unwrap();
+        let threes = board.threes(Player::X);
+        assert_eq!(threes.len(), 2);
+        let defences = Defences::of(&threes);
+        assert_eq!(defences.all, vec![[8, 7]]);
+        assert_eq!(defences.any.len(), 7);
+        assert_eq!(board.actions(), vec![[8, 7]]);
+
+        // with no stone stopping both, O may stop either, the column being short of room at the
+        // edge is stopped from further away
+        let text = "o13o/1o13/15/15/15/15/15/5xxx7/15/15/12x2/12x2/12x2/15/o13o o";
+        let mut board: Board = text.parse().unwrap();
+        let defences = Defences::of(&board.threes(Player::X));
+        assert!(defences.all.is_empty());
+        assert_eq!(defences.any.len(), 9);
+        let mut actions = board.actions();
+        actions.sort();
+        let mut any = defences.any;
+        any.sort();
+        assert_eq!(actions, any);
+
+        // the player to move makes an open four rather than stop the opponent's three
+        let text = "15/15/15/15/12o2/12o2/12o2/5xxx7/15/15/15/15/15/15/15 o";
+        let mut board: Board = text.parse().unwrap();
+        assert_eq!(board.actions(), vec![[12, 3], [12, 7]]);
     }
 }
@@ -0,0 +1,304 @@
+use super::{BoardValue, Grid, Move};
+
+const DIRECTIONS: [(isize, isize); 4] = [(1, 0), (0, 1), (1, 1), (1, -1)];
+
+/// Finds the points Black (X) isn't allowed to play under Renju rules: moves making a
+/// double-three, a double-four or an overline, unless they also make exactly five
+pub struct ForbiddenChecker<G: Grid> {
+    grid: G,
+    width: usize,
+    height: usize,
+}
+impl<G: Grid> ForbiddenChecker<G> {
+    pub fn new(grid: &G, width: usize, height: usize) -> ForbiddenChecker<G> {
+        ForbiddenChecker {
+            grid: grid.clone(),
+            width,
+            height,
+        }
+    }
+
+    pub fn is_forbidden(&mut self, m: Move) -> bool {
+        let [x, y] = m;
+        if self.grid[[y, x]] != BoardValue::Empty {
+            return false;
+        }
+        self.grid.set([y, x], BoardValue::X);
+        let forbidden = self.check_placed(m);
+        self.grid.set([y, x], BoardValue::Empty);
+        forbidden
+    }
+
+    fn check_placed(&mut self, m: Move) -> bool {
+        let runs = DIRECTIONS.map(|d| self.run_length(m, d));
+        if runs.contains(&5) {
+            return false;
+        }
+        if runs.iter().any(|&run| run > 5) {
+            return true;
+        }
+        let fours: usize = DIRECTIONS.iter().map(|&d| self.count_fours(m, d)).sum();
+        if fours >= 2 {
+            return true;
+        }
+        let mut threes = 0;
+        for d in DIRECTIONS {
+            if self.is_open_three(m, d) {
+                threes += 1;
+            }
+        }
+        threes >= 2
+    }
+
+    /// Point t steps away from m along d, `None` if it is off the board
+    fn step(&self, m: Move, d: (isize, isize), t: isize) -> Option<Move> {
+        let x = m[0].checked_add_signed(d.0 * t)?;
+        let y = m[1].checked_add_signed(d.1 * t)?;
+        if x < self.width && y < self.height {
+            Some([x, y])
+        } else {
+            None
+        }
+    }
+
+    fn get(&self, m: Move, d: (isize, isize), t: isize) -> Option<BoardValue> {
+        self.step(m, d, t).map(|[x, y]| self.grid[[y, x]])
+    }
+
+    /// Number of black stones in the unbroken line through m along d
+    fn run_length(&self, m: Move, d: (isize, isize)) -> usize {
+        let mut count = 1;
+        for sign in [1, -1] {
+            let mut t = sign;
+            while self.get(m, d, t) == Some(BoardValue::X) {
+                count += 1;
+                t += sign;
+            }
+        }
+        count
+    }
+
+    /// Offsets along d of the empty points that turn the stones through m into exactly five
+    fn five_points(&mut self, m: Move, d: (isize, isize)) -> Vec<isize> {
+        let mut points = Vec::new();
+        for t in -4..=4isize {
+            let Some([px, py]) = self.step(m, d, t) else {
+                continue;
+            };
+            if self.grid[[py, px]] != BoardValue::Empty {
+                continue;
+            }
+            // every point between p and m has to be black for the five to go through m
+            let between_black =
+                (1..t.abs()).all(|i| self.get(m, d, i * t.signum()) == Some(BoardValue::X));
+            if !between_black {
+                continue;
+            }
+            self.grid.set([py, px], BoardValue::X);
+            if self.run_length([px, py], d) == 5 {
+                points.push(t);
+            }
+            self.grid.set([py, px], BoardValue::Empty);
+        }
+        points
+    }
+
+    /// Number of fours through m along d, B_BBB_B counts as two
+    fn count_fours(&mut self, m: Move, d: (isize, isize)) -> usize {
+        match self.five_points(m, d)[..] {
+            [] => 0,
+            [a, b] if (a - b).abs() == 5 => 1, // straight four
+            ref points => usize::min(points.len(), 2),
+        }
+    }
+
+    fn is_straight_four(&mut self, m: Move, d: (isize, isize)) -> bool {
+        matches!(self.five_points(m, d)[..], [a, b] if (a - b).abs() == 5)
+    }
+
+    /// Whether the stones through m along d form a three that can become a straight four with a
+    /// move that isn't forbidden itself
+    fn is_open_three(&mut self, m: Move, d: (isize, isize)) -> bool {
+        for t in -4..=4isize {
+            let Some(p) = self.step(m, d, t) else {
+                continue;
+            };
+            let [px, py] = p;
+            if self.grid[[py, px]] != BoardValue::Empty {
+                continue;
+            }
+            self.grid.set([py, px], BoardValue::X);
+            let straight_four = self.is_straight_four(m, d);
+            self.grid.set([py, px], BoardValue::Empty);
+            if straight_four && !self.is_forbidden(p) {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::board::{Board, BoardValue, Grid, Move, PlacingError, Player, Rule};
+
+    /// Renju board with the given stones and Black to move, each diagram below is drawn with
+    /// `X` for black, `O` for white and `*` for the point being tested
+    fn board_with(black: &[Move], white: &[Move]) -> Board {
+        let mut board = Board::with_rule(15, 15, Rule::Renju);
+        for &[x, y] in black {
+            board.grid.set([y, x], BoardValue::X);
+        }
+        for &[x, y] in white {
+            board.grid.set([y, x], BoardValue::O);
+        }
+        board.player = Player::X;
+        board
+    }
+
+    #[test]
+    fn test_double_three() {
+        // . X . .
+        // . X . .
+        // * . X X
+        let board = board_with(&[[8, 5], [8, 6], [9, 7], [10, 7]], &[]);
+        assert!(board.is_forbidden([8, 7]));
+        assert!(!board.is_forbidden([7, 7]));
+    }
+
+    #[test]
+    fn test_split_double_three() {
+        // X . * . X X
+        //     X
+        //     X
+        let board = board_with(&[[5, 7], [9, 7], [10, 7], [7, 8], [7, 9]], &[]);
+        // X_*_XX isn't a three, it takes two stones to make a straight four
+        assert!(!board.is_forbidden([7, 7]));
+        // X . * X
+        //     X
+        //     X
+        let board = board_with(&[[5, 7], [8, 7], [7, 8], [7, 9]], &[]);
+        assert!(board.is_forbidden([7, 7]));
+    }
+
+    #[test]
+    fn test_blocked_three_is_not_three() {
+        // O X X * . .
+        //       X
+        //       X
+        let board = board_with(&[[5, 7], [6, 7], [7, 8], [7, 9]], &[[4, 7]]);
+        assert!(!board.is_forbidden([7, 7]));
+    }
+
+    #[test]
+    fn test_three_needing_forbidden_point_is_not_three() {
+        let three = [[6, 7], [8, 7], [7, 8], [7, 9]];
+        let board = board_with(&three, &[]);
+        assert!(board.is_forbidden([7, 7]));
+        // X X . X X X
+        // . X * X . .
+        // . . X . . .
+        // . . X . . .
+        // X X . X X X
+        // both points turning the vertical three into a straight four make overlines
+        let overlines = [
+            [5, 6],
+            [6, 6],
+            [8, 6],
+            [9, 6],
+            [10, 6],
+            [5, 10],
+            [6, 10],
+            [8, 10],
+            [9, 10],
+            [10, 10],
+        ];
+        let board = board_with(&[&three[..], &overlines[..]].concat(), &[]);
+        assert!(board.is_forbidden([7, 6]));
+        assert!(!board.is_forbidden([7, 7]));
+    }
+
+    #[test]
+    fn test_double_four() {
+        // X X X * in both directions, each blocked on one side
+        let board = board_with(
+            &[[4, 7], [5, 7], [6, 7], [7, 4], [7, 5], [7, 6]],
+            &[[3, 7], [7, 3]],
+        );
+        assert!(board.is_forbidden([7, 7]));
+    }
+
+    #[test]
+    fn test_double_four_in_one_line() {
+        // X . X X * . X
+        let board = board_with(&[[3, 7], [5, 7], [6, 7], [9, 7]], &[]);
+        assert!(board.is_forbidden([7, 7]));
+        // X O X X * . X
+        let board = board_with(&[[3, 7], [5, 7], [6, 7], [9, 7]], &[[4, 7]]);
+        assert!(!board.is_forbidden([7, 7]));
+    }
+
+    #[test]
+    fn test_four_three_allowed() {
+        let board = board_with(&[[4, 7], [5, 7], [6, 7], [7, 5], [7, 6]], &[[3, 7]]);
+        assert!(!board.is_forbidden([7, 7]));
+    }
+
+    #[test]
+    fn test_overline() {
+        // X X X * X X
+        let board = board_with(&[[4, 7], [5, 7], [6, 7], [8, 7], [9, 7]], &[]);
+        assert!(board.is_forbidden([7, 7]));
+    }
+
+    #[test]
+    fn test_five_beats_forbidden() {
+        // exactly five horizontally while also making a double four
+        let board = board_with(
+            &[
+                [3, 7],
+                [4, 7],
+                [5, 7],
+                [6, 7],
+                [7, 4],
+                [7, 5],
+                [7, 6],
+                [8, 8],
+                [9, 9],
+            ],
+            &[[7, 3], [10, 10]],
+        );
+        assert!(!board.is_forbidden([7, 7]));
+    }
+
+    #[test]
+    fn test_white_has_no_forbidden_moves() {
+        let mut board = board_with(&[], &[[8, 5], [8, 6], [9, 7], [10, 7]]);
+        board.player = Player::O;
+        assert!(!board.is_forbidden([8, 7]));
+        board.place([8, 7]).unwrap();
+    }
+
+    #[test]
+    fn test_place_and_actions() {
+        let mut board = board_with(&[[8, 5], [8, 6], [9, 7], [10, 7]], &[[0, 0]]);
+        assert!(!board.actions().contains(&[8, 7]));
+        assert!(!board.legal_moves().contains(&[8, 7]));
+        assert!(matches!(board.place([8, 7]), Err(PlacingError::Forbidden)));
+        board.place([7, 7]).unwrap();
+    }
+
+    #[test]
+    fn test_overline_wins_only_for_white() {
+        let mut board = Board::with_rule(15, 15, Rule::Renju);
+        for x in [0, 1, 2, 4, 5] {
+            board.place([x, 0]).unwrap();
+            board.place([x, 14]).unwrap();
+        }
+        assert_eq!(board.utility(Player::X), None);
+        assert!(matches!(board.place([3, 0]), Err(PlacingError::Forbidden)));
+        board.place([7, 7]).unwrap();
+        board.place([3, 14]).unwrap();
+        assert_eq!(board.utility(Player::O), Some(1.0));
+    }
+}
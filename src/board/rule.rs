@@ -0,0 +1,150 @@
+use std::{fmt::Display, str::FromStr};
+
+use super::Player;
+
+/// Rule set deciding which lines win and which moves are allowed
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Rule {
+    /// k or more in a row wins
+    Freestyle,
+    /// Exactly k in a row wins, longer lines (overlines) don't
+    Standard,
+    /// k wins unless blocked at both ends, overline_wins decides whether longer lines win
+    /// regardless of blocking
+    Caro { overline_wins: bool },
+    /// Black (X) wins only with exactly five and may not make double-threes, double-fours or
+    /// overlines, White (O) wins with five or more, only played with k = 5
+    Renju,
+    /// k or more in a row wins, six by default, Black (X) opens with one stone and then both
+    /// players place two stones per turn
+    Connect6,
+}
+impl Default for Rule {
+    fn default() -> Self {
+        Rule::Caro {
+            overline_wins: true,
+        }
+    }
+}
+impl Rule {
+    /// Whether a line of count stones of player wins when k in a row is needed, blocked is true
+    /// when both ends of the line are closed by the opponent or the edge of the board
+    pub fn is_win(&self, count: usize, blocked: bool, player: Player, k: usize) -> bool {
+        match self {
+            Rule::Freestyle | Rule::Connect6 => count >= k,
+            Rule::Standard => count == k,
+            Rule::Caro { overline_wins } => {
+                (count == k && !blocked) || (*overline_wins && count > k)
+            }
+            Rule::Renju => match player {
+                Player::X => count == k,
+                Player::O => count >= k,
+            },
+        }
+    }
+
+    /// Number in a row needed to win unless the board says otherwise
+    pub fn default_win_length(&self) -> usize {
+        match self {
+            Rule::Connect6 => 6,
+            _ => 5,
+        }
+    }
+
+    /// Stones placed per turn after the first, which is always a single stone
+    pub fn stones_per_turn(&self) -> usize {
+        match self {
+            Rule::Connect6 => 2,
+            _ => 1,
+        }
+    }
+
+    /// Whether some of player's moves may be forbidden
+    pub fn has_forbidden_moves(&self, player: Player) -> bool {
+        *self == Rule::Renju && player == Player::X
+    }
+
+    /// Fewest points between the opponent's stones or the edges a line needs to ever hold a win
+    /// of k in a row
+    pub fn span(&self, k: usize) -> usize {
+        match self {
+            // a line filling the whole space would be blocked at both ends
+            Rule::Caro { .. } => k + 1,
+            _ => k,
+        }
+    }
+}
+
+/// Names of the rules in game records, Caro with exact fives is `caro-exact`
+impl Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Rule::Freestyle => "freestyle",
+            Rule::Standard => "standard",
+            Rule::Caro {
+                overline_wins: true,
+            } => "caro",
+            Rule::Caro {
+                overline_wins: false,
+            } => "caro-exact",
+            Rule::Renju => "renju",
+            Rule::Connect6 => "connect6",
+        })
+    }
+}
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "freestyle" => Ok(Rule::Freestyle),
+            "standard" => Ok(Rule::Standard),
+            "caro" => Ok(Rule::Caro {
+                overline_wins: true,
+            }),
+            "caro-exact" => Ok(Rule::Caro {
+                overline_wins: false,
+            }),
+            "renju" => Ok(Rule::Renju),
+            "connect6" => Ok(Rule::Connect6),
+            _ => Err(format!("Unknown rule {s}")),
+        }
+    }
+}
+
+/// Opening restrictions on the first player's stones, the first stone goes in the centre and the
+/// second (the third stone of the game) has to be far from it
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Restriction {
+    /// Second stone at least 3 intersections from the centre
+    Pro,
+    /// Second stone at least 4 intersections from the centre
+    LongPro,
+}
+impl Restriction {
+    pub fn min_distance(&self) -> usize {
+        match self {
+            Restriction::Pro => 3,
+            Restriction::LongPro => 4,
+        }
+    }
+}
+impl Display for Restriction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Restriction::Pro => "pro",
+            Restriction::LongPro => "long-pro",
+        })
+    }
+}
+impl FromStr for Restriction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "pro" => Ok(Restriction::Pro),
+            "long-pro" => Ok(Restriction::LongPro),
+            _ => Err(format!("Unknown restriction {s}")),
+        }
+    }
+}
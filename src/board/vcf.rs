@@ -0,0 +1,261 @@
+//! Victory by continuous fours, a win forced by making a four with every move so the opponent
+//! never gets a free move.
+//!
+//! A four is any move after which the attacker has a winning point, so the rule decides what
+//! counts, a four blocked at both ends doesn't under Caro and a four of Black that is forbidden
+//! can't be played under Renju. The defender only has the moves stopping every winning point,
+//! and loses the line at once with none of them, while a defence making a four of its own has to
+//! be answered by a four that also stops it.
+
+use std::{collections::HashMap, time::Instant};
+
+use super::{Board, BoardValue, Grid, Move, Player};
+
+/// Positions already searched, by hash, with the fours or threats they were searched with
+#[derive(Default)]
+pub(super) struct Solved {
+    /// Lost with this many, so with any fewer
+    pub(super) lost: HashMap<u64, usize>,
+    /// Won with this many, so with any more, and the line winning them
+    pub(super) won: HashMap<u64, (usize, Vec<Move>)>,
+    /// Time to give up at, positions searched after it are taken as lost
+    pub(super) deadline: Option<Instant>,
+}
+
+impl<G: Grid> Board<G> {
+    /// Line of moves winning for the player to move with at most max_fours fours, the
+    /// defender's replies in between. It ends with the five, or with a four the defender can't
+    /// stop. The board is left as it was. Connect6 turns of two stones aren't searched.
+    pub fn vcf(&mut self, max_fours: usize) -> Option<Vec<Move>> {
+        self.vcf_with(max_fours, &mut Solved::default())
+    }
+
+    /// `vcf` giving up at deadline, a line found is still a win
+    pub fn vcf_until(&mut self, max_fours: usize, deadline: Instant) -> Option<Vec<Move>> {
+        let mut solved = Solved {
+            deadline: Some(deadline),
+            ..Solved::default()
+        };
+        self.vcf_with(max_fours, &mut solved)
+    }
+
+    /// `vcf` keeping the positions it solves in solved, for solving the next ones
+    pub(super) fn vcf_with(&mut self, max_fours: usize, solved: &mut Solved) -> Option<Vec<Move>> {
+        if self.rule.stones_per_turn() != 1 || self.result().is_some() {
+            return None;
+        }
+        // short wins are found first and positions lost on the way aren't searched again
+        (0..=max_fours).find_map(|fours| {
+            let mut line = Vec::new();
+            self.vcf_search(self.player, fours, &mut line, solved)
+                .then_some(line)
+        })
+    }
+
+    /// Whether attacker, to move, wins with fours_left fours, pushing the winning line
+    fn vcf_search(
+        &mut self,
+        attacker: Player,
+        fours_left: usize,
+        line: &mut Vec<Move>,
+        solved: &mut Solved,
+    ) -> bool {
+        for m in self.win_points(attacker).clone() {
+            if self.place(m).is_ok() {
+                self.undo();
+                line.push(m);
+                return true;
+            }
+        }
+        let hash = self.hash();
+        if let Some((_, won)) = solved.won.get(&hash).filter(|(n, _)| *n <= fours_left) {
+            line.extend(won);
+            return true;
+        }
+        if fours_left == 0
+            || solved.lost.get(&hash).is_some_and(|&n| n >= fours_left)
+            || solved
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return false;
+        }
+
+        let mut candidates = self.line_candidates(attacker, self.win_length - 2);
+        let threats = self.win_points(attacker.opponent()).clone();
+        if !threats.is_empty() {
+            let defences = self.defences(attacker, &threats);
+            candidates.retain(|m| defences.contains(m));
+        }
+        for m in candidates {
+            if self.place(m).is_err() {
+                continue;
+            }
+            let mut won = vec![m];
+            let is_win = !self.win_points(attacker).is_empty()
+                && self.result().is_none()
+                && self.vcf_defend(attacker, fours_left - 1, &mut won, solved);
+            self.undo();
+            if is_win {
+                line.extend(&won);
+                solved.won.insert(hash, (fours_left, won));
+                return true;
+            }
+        }
+        solved.lost.insert(hash, fours_left);
+        false
+    }
+
+    /// Whether every reply of the defender to attacker's four loses, pushing the line after the
+    /// first reply
+    fn vcf_defend(
+        &mut self,
+        attacker: Player,
+        fours_left: usize,
+        line: &mut Vec<Move>,
+        solved: &mut Solved,
+    ) -> bool {
+        let defender = attacker.opponent();
+        if !self.win_points(defender).is_empty() {
+            // the defender makes five first
+            return false;
+        }
+        let fours = self.win_points(attacker).clone();
+        let mut rest = None;
+        for reply in self.defences(defender, &fours) {
+            if self.place(reply).is_err() {
+                continue;
+            }
+            let mut continuation = vec![reply];
+            let won = self.vcf_search(attacker, fours_left, &mut continuation, solved);
+            self.undo();
+            if !won {
+                return false;
+            }
+            rest.get_or_insert(continuation);
+        }
+        line.extend(rest.unwrap_or_default());
+        true
+    }
+
+    pub(super) fn win_points(&self, player: Player) -> &Vec<Move> {
+        match player {
+            Player::X => &self.x_wins,
+            Player::O => &self.o_wins,
+        }
+    }
+
+    /// Empty points in a stretch of win_length points along a line holding at least stones of
+    /// player's stones and none of the opponent's, with win_length - 2 the points that may make
+    /// a four
+    pub(super) fn line_candidates(&self, player: Player, stones: usize) -> Vec<Move> {
+        let reach = self.win_length - 1;
+        let mut candidates = Vec::new();
+        for y in self.min_y.saturating_sub(reach)..usize::min(self.max_y + reach + 1, self.height) {
+            for x in
+                self.min_x.saturating_sub(reach)..usize::min(self.max_x + reach + 1, self.width)
+            {
+                if self.is_line_candidate([x, y], player, stones) {
+                    candidates.push([x, y]);
+                }
+            }
+        }
+        candidates
+    }
+
+    /// Whether m is one of the `line_candidates`
+    pub(super) fn is_line_candidate(&self, m: Move, player: Player, stones: usize) -> bool {
+        let [x, y] = m;
+        if self.grid[[y, x]] != BoardValue::Empty {
+            return false;
+        }
+        let k = self.win_length as isize;
+        let v = player.board_value();
+        let at = |dx: isize, dy: isize, t: isize| {
+            let px = x.checked_add_signed(dx * t)?;
+            let py = y.checked_add_signed(dy * t)?;
+            (px < self.width && py < self.height).then(|| self.grid[[py, px]])
+        };
+        [(1, 0), (0, 1), (1, 1), (1, -1)].iter().any(|&(dx, dy)| {
+            (1 - k..=0).any(|start| {
+                let mut count = 0;
+                for t in start..start + k {
+                    match at(dx, dy, t) {
+                        Some(p) if p == v => count += 1,
+                        Some(BoardValue::Empty) => {}
+                        _ => return false,
+                    }
+                }
+                count >= stones
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::board::{Board, Move};
+
+    /// Plays line on board, checking that the player to move wins with it
+    fn check_line(mut board: Board, line: &[Move]) {
+        let attacker = board.player;
+        for &m in line {
+            board.place(m).unwrap();
+        }
+        if board.result().is_none() {
+            // the last four can't be stopped
+            board.place_random().unwrap();
+            let win = board.win_points(attacker)[0];
+            board.place(win).unwrap();
+        }
+        assert_eq!(board.utility(attacker), Some(1.0));
+    }
+
+    #[test]
+    fn test_vcf() {
+        // the four at 5,6 makes the column a three, then 5,5 makes two fours at once
+        let text = "15/15/15/15/15/oxxx11/6xxxo5/5x9/5x9/5o9/15/15/2o4o7/15/o4o4o4 x";
+        let mut board: Board = text.parse().unwrap();
+        assert_eq!(board.vcf(1), None);
+        let line = board.vcf(4).unwrap();
+        assert_eq!(line.len() % 2, 1);
+        assert_eq!(board.to_string(), text);
+        // out of time before the first four
+        assert_eq!(board.vcf_until(4, Instant::now()), None);
+        let deadline = Instant::now() + Duration::from_secs(60);
+        assert_eq!(board.vcf_until(4, deadline), Some(line.clone()));
+        check_line(board, &line);
+
+        let mut board: Board = "15/15/15/15/15/15/15/7x7/15/15/15/15/15/15/15 o"
+            .parse()
+            .unwrap();
+        assert_eq!(board.vcf(10), None);
+    }
+
+    #[test]
+    fn test_vcf_answers_fours() {
+        // O has to block X's four first, which is no four of its own
+        let text = "15/15/15/xxxx11/15/15/ooo12/15/15/15/15/15/15/15/15 o";
+        let mut board: Board = text.parse().unwrap();
+        assert_eq!(board.vcf(10), None);
+        // unless it has a five to make
+        let text = "15/15/15/xxxx11/15/15/oooo11/15/15/15/15/15/15/15/15 o";
+        let mut board: Board = text.parse().unwrap();
+        assert_eq!(board.vcf(10), Some(vec![[4, 6]]));
+    }
+
+    #[test]
+    fn test_vcf_rules() {
+        // a five blocked at both ends only wins under Freestyle
+        let text = "15/15/15/oxxxx1o8/15/15/15/15/15/15/15/15/15/15/15 x";
+        let mut board: Board = format!("{text} freestyle").parse().unwrap();
+        assert_eq!(board.vcf(3), Some(vec![[5, 3]]));
+        let mut board: Board = text.parse().unwrap();
+        assert_eq!(board.vcf(3), None);
+
+        let mut board: Board = "7/7/7/7/7/7/7 x connect6".parse().unwrap();
+        assert_eq!(board.vcf(3), None);
+    }
+}
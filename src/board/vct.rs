@@ -0,0 +1,335 @@
+//! Victory by continuous threats, a threat-space search after Allis where the attacker may also
+//! make threes, which give the defender more replies than a four does.
+//!
+//! A three is a move after which the attacker has a point making a four no single stone stops,
+//! an open four. The defender answers it with a stone after which no such point is left, or with
+//! a four of its own that the attacker has to block, after which the three has to be answered
+//! again. Before any of that the defender looks for a four winning at once, which refutes the
+//! three outright. Every threat after the first has to share a line with a stone the attacker
+//! gained earlier in the sequence, the dependency between threats that keeps the search to
+//! combinations that could win; threats on their own are found at the start of some other
+//! sequence.
+
+use std::{collections::HashMap, time::Instant};
+
+use super::{vcf::Solved, zobrist, Board, BoardValue, Grid, Move, Player};
+
+/// Fours the defender looks ahead to refute a three straight away, longer wins by fours are
+/// found among its replies
+const REFUTATION_FOURS: usize = 1;
+
+/// Most positions searched before giving up
+const MAX_POSITIONS: usize = 10_000;
+
+/// Positions solved by the threat-space search, and by the defender's search for fours
+#[derive(Default)]
+struct Caches {
+    /// Won with this many threats, so with any more, and the line winning them
+    won: HashMap<u64, (usize, Vec<Move>)>,
+    /// Lost with this many threats, by hash and by the key of the stones gained before, which
+    /// leave threats out. The same position may be won with other stones gained
+    lost: HashMap<(u64, u64), usize>,
+    refutations: Solved,
+    positions_left: usize,
+    /// Time to give up at, like running out of positions
+    deadline: Option<Instant>,
+}
+
+impl Caches {
+    /// Whether the positions or the time to search them have run out
+    fn is_out_of_time(&self) -> bool {
+        self.positions_left == 0
+            || self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+}
+
+impl<G: Grid> Board<G> {
+    /// Line of moves winning for the player to move with at most max_threats fours and threes,
+    /// the defender's first reply after each. It ends with the five, or with a threat the
+    /// defender can't stop. `None` means no such win was found within max_threats, though one
+    /// with more threats may exist, or one in a search too large to finish. The board is left as
+    /// it was. Connect6 turns of two stones aren't searched.
+    pub fn vct(&mut self, max_threats: usize) -> Option<Vec<Move>> {
+        self.vct_with(max_threats, None)
+    }
+
+    /// `vct` giving up at deadline, a line found is still a win
+    pub fn vct_until(&mut self, max_threats: usize, deadline: Instant) -> Option<Vec<Move>> {
+        self.vct_with(max_threats, Some(deadline))
+    }
+
+    fn vct_with(&mut self, max_threats: usize, deadline: Option<Instant>) -> Option<Vec<Move>> {
+        if self.rule.stones_per_turn() != 1 || self.result().is_some() {
+            return None;
+        }
+        let mut caches = Caches {
+            refutations: Solved {
+                deadline,
+                ..Solved::default()
+            },
+            positions_left: MAX_POSITIONS,
+            deadline,
+            ..Caches::default()
+        };
+        (0..=max_threats).find_map(|threats| {
+            let mut line = Vec::new();
+            self.vct_search(
+                self.player,
+                threats,
+                &mut Vec::new(),
+                &mut line,
+                &mut caches,
+            )
+            .then_some(line)
+        })
+    }
+
+    /// Whether attacker, to move, wins with threats_left threats, pushing the winning line.
+    /// gained holds the attacker's stones placed so far in the sequence
+    fn vct_search(
+        &mut self,
+        attacker: Player,
+        threats_left: usize,
+        gained: &mut Vec<Move>,
+        line: &mut Vec<Move>,
+        caches: &mut Caches,
+    ) -> bool {
+        for m in self.win_points(attacker).clone() {
+            if self.place(m).is_ok() {
+                self.undo();
+                line.push(m);
+                return true;
+            }
+        }
+        let hash = self.hash();
+        if let Some((_, won)) = caches.won.get(&hash).filter(|(n, _)| *n <= threats_left) {
+            line.extend(won);
+            return true;
+        }
+        let gained_key = gained
+            .iter()
+            .fold(0, |key, &g| key ^ zobrist::stone(g, attacker.board_value()));
+        if threats_left == 0
+            || caches
+                .lost
+                .get(&(hash, gained_key))
+                .is_some_and(|&n| n >= threats_left)
+            || caches.is_out_of_time()
+        {
+            return false;
+        }
+        caches.positions_left -= 1;
+
+        let fours = self.win_points(attacker.opponent()).clone();
+        let candidates = if fours.is_empty() {
+            let k = self.win_length;
+            // fours first, then the points that may make a three
+            let mut candidates = self.line_candidates(attacker, k - 2);
+            let mut threes = self.line_candidates(attacker, k - 3);
+            threes.retain(|m| !candidates.contains(m));
+            candidates.extend(threes);
+            if !gained.is_empty() {
+                candidates.retain(|&m| {
+                    gained
+                        .iter()
+                        .any(|&g| self.line_points(g, k - 1).contains(&m))
+                });
+            }
+            candidates
+        } else {
+            // a four of the defender is blocked, the threat it answered has to be still there
+            self.defences(attacker, &fours)
+        };
+        for m in candidates {
+            if self.place(m).is_err() {
+                continue;
+            }
+            // a new three is on the lines of its stone
+            let near = match fours.is_empty() {
+                true => self.line_points(m, self.win_length - 1),
+                false => self.line_candidates(attacker, self.win_length - 2),
+            };
+            let is_threat = self.result().is_none()
+                && (!self.win_points(attacker).is_empty()
+                    || !self.open_four_points(attacker, &near).is_empty());
+            let mut won = vec![m];
+            gained.push(m);
+            let is_win =
+                is_threat && self.vct_defend(attacker, threats_left - 1, gained, &mut won, caches);
+            gained.pop();
+            self.undo();
+            if is_win {
+                line.extend(&won);
+                caches.won.insert(hash, (threats_left, won));
+                return true;
+            }
+        }
+        caches.lost.insert((hash, gained_key), threats_left);
+        false
+    }
+
+    /// Whether every reply of the defender to attacker's threat loses, pushing the line after
+    /// the first reply
+    fn vct_defend(
+        &mut self,
+        attacker: Player,
+        threats_left: usize,
+        gained: &mut Vec<Move>,
+        line: &mut Vec<Move>,
+        caches: &mut Caches,
+    ) -> bool {
+        let defender = attacker.opponent();
+        if !self.win_points(defender).is_empty() || caches.is_out_of_time() {
+            return false;
+        }
+        caches.positions_left -= 1;
+        let fours = self.win_points(attacker).clone();
+        let replies = if !fours.is_empty() {
+            self.defences(defender, &fours)
+        } else {
+            if self
+                .vcf_with(REFUTATION_FOURS, &mut caches.refutations)
+                .is_some()
+            {
+                return false;
+            }
+            let mut replies = self.three_defences(attacker);
+            for m in self.line_candidates(defender, self.win_length - 2) {
+                if replies.contains(&m) || self.place(m).is_err() {
+                    continue;
+                }
+                if !self.win_points(defender).is_empty() {
+                    replies.push(m);
+                }
+                self.undo();
+            }
+            replies
+        };
+
+        let mut rest = None;
+        for reply in replies {
+            if self.place(reply).is_err() {
+                continue;
+            }
+            let mut continuation = vec![reply];
+            let won = self.vct_search(attacker, threats_left, gained, &mut continuation, caches);
+            self.undo();
+            if !won {
+                return false;
+            }
+            rest.get_or_insert(continuation);
+        }
+        line.extend(rest.unwrap_or_default());
+        true
+    }
+
+    /// Empty points among points where a stone of player makes an open four, leaving winning
+    /// points the opponent can't all stop with one stone
+    fn open_four_points(&mut self, player: Player, points: &[Move]) -> Vec<Move> {
+        let mut res = Vec::new();
+        for &q in points {
+            let [x, y] = q;
+            if !self.is_line_candidate(q, player, self.win_length - 2)
+                || self.is_forbidden_for(q, player)
+            {
+                continue;
+            }
+            self.grid.set([y, x], player.board_value());
+            let k = self.win_length;
+            let wins: Vec<Move> = self
+                .line_points(q, k)
+                .into_iter()
+                .filter(|&p| self.wins_at(p, player))
+                .collect();
+            let is_open = wins.len() >= 2 && self.defences(player.opponent(), &wins).is_empty();
+            self.grid.set([y, x], BoardValue::Empty);
+            if is_open {
+                res.push(q);
+            }
+        }
+        res
+    }
+
+    /// Empty points where a stone of the opponent leaves attacker without an open four to make
+    fn three_defences(&mut self, attacker: Player) -> Vec<Move> {
+        let points = self.line_candidates(attacker, self.win_length - 2);
+        let threats = self.open_four_points(attacker, &points);
+        let defender = attacker.opponent();
+        let mut candidates: Vec<Move> = Vec::new();
+        for &q in &threats {
+            for p in self.line_points(q, self.win_length) {
+                if self.get(p) == Some(BoardValue::Empty) && !candidates.contains(&p) {
+                    candidates.push(p);
+                }
+            }
+        }
+        candidates.retain(|&[x, y]| {
+            self.grid.set([y, x], defender.board_value());
+            let stopped = self.open_four_points(attacker, &threats).is_empty();
+            self.grid.set([y, x], BoardValue::Empty);
+            stopped
+        });
+        candidates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::{Caches, MAX_POSITIONS};
+    use crate::board::{Board, Player};
+
+    // X makes two open threes at once with 7,7
+    const DOUBLE_THREE: &str = "7x7/7x7/4xx9/15/15/15/15";
+
+    #[test]
+    fn test_vct() {
+        let text = format!("14o/15/14o/15/15/{DOUBLE_THREE}/o14/15/o14 x");
+        let mut board: Board = text.parse().unwrap();
+        assert_eq!(board.vcf(10), None);
+        assert_eq!(board.vct(3), Some(vec![[7, 7]]));
+        assert_eq!(board.to_string(), text);
+        assert_eq!(board.vct_until(3, Instant::now()), None);
+
+        // whatever O does X has an open three left to make a four of
+        board.place([7, 7]).unwrap();
+        board.place_random().unwrap();
+        assert!(board.vcf(1).is_some());
+
+        let mut board: Board = "15/15/15/15/15/15/15/7x7/15/15/15/15/15/15/15 o"
+            .parse()
+            .unwrap();
+        assert_eq!(board.vct(4), None);
+    }
+
+    #[test]
+    fn test_vct_lost_with_other_gains() {
+        let text = format!("14o/15/14o/15/15/{DOUBLE_THREE}/o14/15/o14 x");
+        let mut board: Board = text.parse().unwrap();
+        let mut caches = Caches {
+            positions_left: MAX_POSITIONS,
+            ..Caches::default()
+        };
+        // a stone gained in the corner keeps the threes in the middle out of the search
+        let mut line = Vec::new();
+        assert!(!board.vct_search(Player::X, 3, &mut vec![[0, 0]], &mut line, &mut caches));
+        assert!(board.vct_search(Player::X, 3, &mut Vec::new(), &mut line, &mut caches));
+        assert!(line.contains(&[7, 7]));
+    }
+
+    #[test]
+    fn test_vct_defences() {
+        // O's open three wins by fours before the threes of X come to anything
+        let text = format!("15/3ooo9/15/15/15/{DOUBLE_THREE}/15/15/o14 x");
+        let mut board: Board = text.parse().unwrap();
+        assert_eq!(board.vct(4), None);
+
+        // a four of O only delays them, X blocks it and the threes are still there
+        let text = format!("14o/xooo11/15/15/15/{DOUBLE_THREE}/15/15/o14 x");
+        let mut board: Board = text.parse().unwrap();
+        assert_eq!(board.vct(4), Some(vec![[7, 7], [4, 1], [5, 1]]));
+    }
+}
@@ -0,0 +1,82 @@
+//! Zobrist keys, one pseudo-random number per point and colour. The keys are generated on the fly
+//! from a fixed seed so that hashes agree between boards, runs and machines.
+
+use super::{BoardValue, Move, Player};
+
+const SEED: u64 = 0x3c6e_f372_fe94_f82b;
+
+/// splitmix64, turning consecutive integers into well mixed 64 bit values
+fn mix(n: u64) -> u64 {
+    let mut z = n.wrapping_add(SEED).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Key of a stone of value v at m
+pub fn stone(m: Move, v: BoardValue) -> u64 {
+    let colour = match v {
+        BoardValue::X => 0,
+        BoardValue::O => 1,
+        BoardValue::Empty => return 0,
+    };
+    // 1 + so that the turn key below never collides with a stone's
+    let [x, y] = m.map(|c| c as u64);
+    mix(1 + ((y << 32 | x) << 1 | colour))
+}
+
+/// Key of the player to move
+pub fn turn(player: Player) -> u64 {
+    match player {
+        Player::X => 0,
+        Player::O => mix(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::board::{Board, Move, Player, Rule};
+
+    fn hash_after(board: &Board, moves: &[Move]) -> u64 {
+        let mut board = board.clone();
+        for &m in moves {
+            board.place(m).unwrap();
+        }
+        board.hash()
+    }
+
+    #[test]
+    fn test_permutations_hash_alike() {
+        let board = Board::new(15, 15);
+        let x_stones = [[7, 7], [8, 8], [6, 9]];
+        let o_stones = [[7, 8], [9, 9], [0, 14]];
+        let interleave = |x: [Move; 3], o: [Move; 3]| [x[0], o[0], x[1], o[1], x[2], o[2]];
+        let expected = hash_after(&board, &interleave(x_stones, o_stones));
+        for x_order in [[0, 1, 2], [2, 1, 0], [1, 2, 0]] {
+            for o_order in [[0, 1, 2], [1, 0, 2], [2, 0, 1]] {
+                let x = x_order.map(|i| x_stones[i]);
+                let o = o_order.map(|i| o_stones[i]);
+                assert_eq!(hash_after(&board, &interleave(x, o)), expected);
+            }
+        }
+        // same stones, other colours
+        assert_ne!(
+            hash_after(&board, &interleave(o_stones, x_stones)),
+            expected
+        );
+        assert_ne!(board.hash(), expected);
+        // same stones, other player to move
+        let mut o_to_move = board.clone();
+        o_to_move.player = Player::O;
+        assert_ne!(o_to_move.hash(), board.hash());
+        assert_eq!(board.hash(), Board::new(19, 19).hash());
+    }
+
+    #[test]
+    fn test_connect6_pairs_hash_alike() {
+        let board = Board::with_rule(19, 19, Rule::Connect6);
+        let first = hash_after(&board, &[[9, 9], [3, 3], [4, 4], [5, 5], [6, 6]]);
+        let second = hash_after(&board, &[[9, 9], [4, 4], [3, 3], [6, 6], [5, 5]]);
+        assert_eq!(first, second);
+    }
+}
@@ -0,0 +1,26 @@
+//! Caro/Gomoku engine based on Monte Carlo Tree Search.
+//!
+//! A game is played on a [`Board`], the engine searches moves with a [`SearchTree`]:
+//! ```
+//! use caro_ai::{Board, SearchLimits, SearchTree};
+//!
+//! let mut board = Board::new(15, 15);
+//! board.place([7, 7]).unwrap();
+//! let mut search_tree = SearchTree::new(board.clone());
+//! let result = search_tree.search(SearchLimits::iterations(10));
+//! board.place(result.best_move.unwrap()).unwrap();
+//! ```
+
+pub mod board;
+pub mod mcts;
+pub mod opening;
+pub mod protocol;
+pub mod record;
+
+pub use board::{
+    ArrayGrid, BitGrid, Board, BoardValue, Defences, Direction, Grid, Move, Outcome, PlacingError,
+    Player, Restriction, Rule, Threat, Three, Util,
+};
+pub use mcts::{
+    MoveStats, Parallelism, SearchConfig, SearchLimits, SearchResult, SearchStats, SearchTree,
+};
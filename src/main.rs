@@ -1,15 +1,22 @@
-use std::io::{stdin, stdout, Write};
+use std::{
+    env, fs,
+    io::{self, stdin, stdout, Write},
+    path::Path,
+    time::Duration,
+};
 
-use board::Board;
-use mcts::SearchTree;
+use caro_ai::{
+    opening::{self, Opening, OpeningRule, Seat, SwapChoice},
+    protocol::Brain,
+    record::{sgf::SgfGame, GameRecord},
+    ArrayGrid, BitGrid, Board, Grid, Move, Player, Restriction, SearchLimits, SearchTree,
+};
 
-use crate::board::Player;
-
-mod board;
-mod mcts;
 const WIDTH: usize = 15;
 const HEIGHT: usize = 15;
 const MAX_TIME_LIMIT: f32 = 20.0;
+const OPENING_TIME_LIMIT: Duration = Duration::from_secs(1); // per evaluated position
+const VCF_FOURS: usize = 20;
 
 fn parse_move(cmd: &str) -> Result<[usize; 2], String> {
     if cmd.len() != 2 {
@@ -27,61 +34,316 @@ fn parse_move(cmd: &str) -> Result<[usize; 2], String> {
     }
 }
 
+/// Seconds to think about the engine's move_number-th move, longer as the game goes on
+fn time_limit(move_number: usize) -> f32 {
+    MAX_TIME_LIMIT * (1.0 - 7.0 / (move_number as f32 + 6.7))
+}
+
+/// Moves are typed one-based
+fn to_move([x, y]: [usize; 2]) -> Move {
+    [
+        x.checked_sub(1).unwrap_or(WIDTH),
+        y.checked_sub(1).unwrap_or(HEIGHT),
+    ]
+}
+
+fn read_command(prompt: &str) -> String {
+    print!("{prompt}");
+    stdout().flush().expect("Error when printing text");
+    let mut cmd = String::new();
+    stdin()
+        .read_line(&mut cmd)
+        .expect("Error when reading command");
+    cmd.trim().to_string()
+}
+
+/// Opening rule picked with `--swap` or `--swap2`
+fn opening_rule() -> Option<OpeningRule> {
+    env::args().skip(1).find_map(|arg| match arg.as_str() {
+        "--swap" => Some(OpeningRule::Swap),
+        "--swap2" => Some(OpeningRule::Swap2),
+        _ => None,
+    })
+}
+
+/// Opening restriction picked with `--pro` or `--long-pro`
+fn restriction() -> Option<Restriction> {
+    env::args().skip(1).find_map(|arg| match arg.as_str() {
+        "--pro" => Some(Restriction::Pro),
+        "--long-pro" => Some(Restriction::LongPro),
+        _ => None,
+    })
+}
+
+/// Whether the engine searches on bitboards, picked with `--bitboard`
+fn is_bitboard() -> bool {
+    env::args().skip(1).any(|arg| arg == "--bitboard")
+}
+
+/// Value passed after the option name, like `--resume <file>`
+fn option_value(name: &str) -> Option<String> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    args.windows(2)
+        .find(|args| args[0] == name)
+        .map(|args| args[1].clone())
+}
+
+/// File of the game to continue, passed with `--resume <file>`
+fn resume_path() -> Option<String> {
+    option_value("--resume")
+}
+
+/// Position to start from in the compact notation, passed with `--position <position>`
+fn start_position() -> Option<String> {
+    option_value("--position")
+}
+
+fn is_sgf(path: &str) -> bool {
+    path.to_lowercase().ends_with(".sgf")
+}
+
+/// Game in an SGF file, or else in the plain text format
+fn load_game(path: &str) -> Result<Board, String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let board = if is_sgf(path) {
+        text.parse::<SgfGame>().and_then(|game| game.to_board())
+    } else {
+        text.parse::<GameRecord>()
+            .and_then(|record| record.to_board())
+    };
+    board.map_err(|e| e.to_string())
+}
+
+fn save_game(board: &Board, path: &str) -> io::Result<()> {
+    let text = if is_sgf(path) {
+        SgfGame::from_board(board)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
+            .to_string()
+    } else {
+        GameRecord::from_board(board).to_string()
+    };
+    fs::write(path, text)
+}
+
+/// The engine places the opening and the player chooses, returns the board to continue from and
+/// the player's colour
+fn play_opening(rule: OpeningRule, board: Board) -> (Board, Player) {
+    let limits = SearchLimits::time(OPENING_TIME_LIMIT);
+    let mut opening = Opening::new(rule, board);
+    let stones = opening::propose_opening(opening.board(), limits);
+    opening
+        .place_opening(stones)
+        .expect("engine proposed an illegal opening");
+
+    let prompt = match rule {
+        OpeningRule::Swap => "PICK X OR O:",
+        OpeningRule::Swap2 => "PICK X OR O, OR TYPE A WHITE AND A BLACK MOVE:",
+    };
+    while opening.to_choose() == Some(Seat::Chooser) {
+        println!("{}", opening.board().diagram());
+        let cmd = read_command(prompt).to_uppercase();
+        let choice = match cmd.as_str() {
+            "X" => SwapChoice::Colour(Player::X),
+            "O" => SwapChoice::Colour(Player::O),
+            _ => {
+                let moves: Result<Vec<_>, _> = cmd.split_whitespace().map(parse_move).collect();
+                match moves.as_deref() {
+                    Ok(&[white, black]) => SwapChoice::AddStones([to_move(white), to_move(black)]),
+                    Ok(_) => {
+                        println!("Expected X, O or two moves");
+                        continue;
+                    }
+                    Err(e) => {
+                        println!("{e}");
+                        continue;
+                    }
+                }
+            }
+        };
+        if let Err(e) = opening.choose(choice) {
+            println!("{e}");
+        }
+    }
+    if let Some(choice) = opening.decide(limits) {
+        if let SwapChoice::Colour(colour) = choice {
+            println!("ENGINE PLAYS {colour:?}");
+        }
+        opening
+            .choose(choice)
+            .expect("engine made an illegal choice");
+    }
+    let human = opening.colour(Seat::Chooser).unwrap();
+    (opening.into_board(), human)
+}
+
+/// Print the win by continuous fours of the player to move in the position passed with
+/// `--vcf <position>`
+fn solve_vcf(position: &str) {
+    let mut board: Board = match position.parse() {
+        Ok(board) => board,
+        Err(e) => {
+            println!("Unable to read the position: {e}");
+            return;
+        }
+    };
+    println!("{}", board.diagram());
+    match board.vcf(VCF_FOURS) {
+        Some(line) => println!("{:?} WINS BY FOURS: {line:?}", board.player),
+        None => println!("No win by {VCF_FOURS} fours or less for {:?}", board.player),
+    }
+}
+
+/// Gomocup managers expect brains to be named `pbrain-*` and start them without arguments
+fn is_protocol_mode() -> bool {
+    let mut args = env::args();
+    let is_pbrain = args
+        .next()
+        .as_deref()
+        .and_then(|exe| Path::new(exe).file_name())
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with("pbrain"));
+    is_pbrain || args.any(|arg| arg == "--protocol")
+}
+
 fn main() {
+    if is_protocol_mode() {
+        Brain::new()
+            .run(stdin().lock(), stdout().lock())
+            .expect("Error when talking to the manager");
+        return;
+    }
+    if let Some(position) = option_value("--vcf") {
+        solve_vcf(&position);
+        return;
+    }
+
     let mut board = Board::new(WIDTH, HEIGHT);
-    let mut search_tree = SearchTree::new(board.clone());
-    let mut move_number = 0;
+    if let Some(restriction) = restriction() {
+        board = board.with_restriction(restriction);
+    }
+    let mut human = Player::X;
+    let loaded = match (resume_path(), start_position()) {
+        (Some(path), _) => Some(load_game(&path).map_err(|e| format!("{path}: {e}"))),
+        (None, Some(position)) => {
+            // the restriction is part of the position, `--pro` adds it to one without
+            let position = match restriction() {
+                Some(restriction) => format!("{position} {restriction}"),
+                None => position,
+            };
+            Some(
+                position
+                    .parse::<Board>()
+                    .map_err(|e| format!("position: {e}")),
+            )
+        }
+        (None, None) => None,
+    };
+    if let Some(loaded) = loaded {
+        // the player to move in the saved game or position is the human
+        board = match loaded {
+            Ok(board) => board,
+            Err(e) => {
+                println!("Unable to start from {e}");
+                return;
+            }
+        };
+        if let Some(outcome) = board.result() {
+            println!("{}", board.diagram());
+            println!("Game is already over: {outcome:?}");
+            return;
+        }
+        human = board.player;
+    } else if let Some(rule) = opening_rule() {
+        (board, human) = play_opening(rule, board);
+    }
+    // opening stones can't be taken back
+    let game_start = match resume_path() {
+        Some(_) => 0,
+        None => board.history().count(),
+    };
+    if is_bitboard() {
+        play::<BitGrid>(board, human, game_start);
+    } else {
+        play::<ArrayGrid>(board, human, game_start);
+    }
+}
+
+/// Play the game against the engine searching on a G, the moves before game_start can't be taken
+/// back
+fn play<G: Grid>(mut board: Board, human: Player, game_start: usize) {
+    let engine = human.opponent();
+    let mut search_tree = SearchTree::new(board.clone().into_grid::<G>());
+    // a resumed game goes on from the moves already played in it
+    let mut move_number = (board.history().count() - game_start) / 2;
+    if board.player == engine {
+        let m = search_tree.monte_carlo(time_limit(1));
+        board.place(m).unwrap();
+        search_tree.apply_move(m);
+    }
     loop {
-        println!("{board}");
+        println!("{}", board.diagram());
         // println!("{:?}", board.utility(board::Player::X));
         // println!("{:?}", board.actions());
         // println!("{search_tree}");
-        print!("X TO MOVE:");
-        stdout().flush().expect("Error when printing text");
-        let mut cmd = String::new();
-        stdin()
-            .read_line(&mut cmd)
-            .expect("Error when reading command");
-        let cmd = cmd.trim();
+        let cmd = read_command(&format!("{human:?} TO MOVE:"));
         if cmd.to_uppercase() == "Q" {
             break;
         }
+        if let Some(path) = cmd.strip_prefix("s ").or(cmd.strip_prefix("S ")) {
+            match save_game(&board, path.trim()) {
+                Ok(()) => println!("Saved to {}", path.trim()),
+                Err(e) => println!("{e}"),
+            }
+            continue;
+        }
+        if cmd.to_uppercase() == "P" {
+            println!("{board}");
+            continue;
+        }
+        if cmd.to_uppercase() == "U" {
+            // the engine's reply and the player's move before it
+            if board.history().count() < game_start + 2 {
+                println!("Nothing to take back");
+                continue;
+            }
+            for _ in 0..2 {
+                board.undo();
+                search_tree.undo_move();
+            }
+            move_number -= 1;
+            continue;
+        }
 
-        let [x, y] = match parse_move(cmd.trim()) {
-            Ok(m) => m,
+        let m = match parse_move(&cmd) {
+            Ok(m) => to_move(m),
             Err(e) => {
                 println!("{e}");
                 board.place_random().unwrap();
                 continue;
             }
         };
-        let m = [
-            x.checked_sub(1).unwrap_or(WIDTH),
-            y.checked_sub(1).unwrap_or(HEIGHT),
-        ];
         if let Err(e) = board.place(m) {
             println!("{e}");
             continue;
         }
-        if board.utility(Player::X).is_some() {
-            println!("{board}");
-            println!("X WINS");
+        if board.utility(human).is_some() {
+            println!("{}", board.diagram());
+            println!("{human:?} WINS");
             break;
         }
 
         search_tree.apply_move(m);
         move_number += 1;
-        let time_limit = MAX_TIME_LIMIT * (1.0 - 7.0 / (move_number as f32 + 6.7));
         // search for move using mcts
-        let m = search_tree.monte_carlo(time_limit);
+        let m = search_tree.monte_carlo(time_limit(move_number));
         // print!("{search_tree}");
         if let Err(e) = board.place(m) {
             println!("{e}");
             continue;
         }
-        if board.utility(Player::O).is_some() {
-            println!("{board}");
-            println!("O WINS");
+        if board.utility(engine).is_some() {
+            println!("{}", board.diagram());
+            println!("{engine:?} WINS");
             break;
         }
         search_tree.apply_move(m);
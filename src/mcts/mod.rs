@@ -0,0 +1,1044 @@
+use std::{
+    cmp::Reverse,
+    collections::VecDeque,
+    f32::consts::SQRT_2,
+    fmt::Display,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, RwLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use uuid::Uuid;
+
+use crate::board::{ArrayGrid, Board, Grid, Move, Player, Util};
+use pool::ThreadPool;
+use table::TranspositionTable;
+
+mod pool;
+mod table;
+
+const C: f32 = SQRT_2;
+const SIMULATE_CUTOFF: usize = 82;
+const HEURISTIC_WEIGHT: f32 = 0.1;
+const VIRTUAL_LOSS: usize = 3;
+/// Roots left by `apply_move` that `undo_move` goes back to with their statistics, older positions
+/// are searched again from scratch
+const KEPT_ROOTS: usize = 4;
+/// Part of the time limit the forced wins may take to look for before the tree search
+const FORCED_WIN_TIME_SHARE: u32 = 4;
+
+/// Play a random game from state, return its utility for player
+fn playout<G: Grid>(mut state: Board<G>, player: Player) -> Util {
+    let mut util = state.utility(player);
+    let mut num_moves_simulated = 0;
+    while util.is_none() {
+        if num_moves_simulated > SIMULATE_CUTOFF {
+            // keep the utilities of both players summing to 1
+            let h = state.heuristic(player);
+            return 0.5 + (h - 0.5) * HEURISTIC_WEIGHT;
+        }
+        if state.place_random().is_err() {
+            // only forbidden points are left
+            return 0.5;
+        }
+        util = state.utility(player);
+        num_moves_simulated += 1;
+    }
+    util.unwrap()
+}
+
+struct Node<G: Grid = ArrayGrid> {
+    state: Board<G>,
+    /// Moves and the nodes they lead to, with transpositions a node may be the child of several
+    children: RwLock<Vec<(Move, Arc<Node<G>>)>>,
+    mover: Player, // player who made the last move
+    terminal: bool,
+    threats: bool,
+    u: AtomicU64,   // total utility for mover, bits of an f64
+    n: AtomicUsize, // total playous, including virtual losses of searches passing through
+}
+impl<G: Grid> Node<G> {
+    fn new(mut state: Board<G>, mover: Player) -> Node<G> {
+        let terminal = state.utility(mover).is_some();
+        let threats = !terminal && state.are_there_threats();
+        Node {
+            state,
+            children: RwLock::new(Vec::new()),
+            mover,
+            terminal,
+            threats,
+            u: AtomicU64::new(0.0f64.to_bits()),
+            n: AtomicUsize::new(0),
+        }
+    }
+
+    fn new_root(init_state: Board<G>) -> Node<G> {
+        let mover = init_state.last_mover();
+        Node::new(init_state, mover)
+    }
+
+    fn n(&self) -> usize {
+        self.n.load(Ordering::Relaxed)
+    }
+
+    fn u(&self) -> Util {
+        f64::from_bits(self.u.load(Ordering::Relaxed)) as Util
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.children.read().unwrap().is_empty()
+    }
+
+    /// Generate children of this node for every move, taking the nodes of positions already in
+    /// table if given, return an arbitary child or `None` if there are no moves
+    fn expand(&self, table: Option<&TranspositionTable<G>>) -> Option<Arc<Node<G>>> {
+        let mut children = self.children.write().unwrap();
+        // another search may have expanded this node while we waited for the lock
+        if children.is_empty() {
+            for m in self.state.clone().actions() {
+                let mut new_board = self.state.clone();
+                new_board
+                    .place(m)
+                    .expect("can't do move when expanding node");
+                let mover = self.state.player;
+                let child = match table {
+                    Some(table) => table.get_or_insert(new_board, mover),
+                    None => Arc::new(Node::new(new_board, mover)),
+                };
+                children.push((m, child));
+            }
+        }
+        if children.is_empty() {
+            return None;
+        }
+        Some(children[fastrand::usize(..children.len())].1.clone())
+    }
+
+    /// Play n game on the pool, return the total utility of those games for mover
+    fn simulate(&self, pool: &ThreadPool, n: usize) -> Util {
+        // println!("Simulating node with prev_action: {:?}", self.prev_action);
+        let player = self.mover;
+        let jobs = (0..n).map(|_| {
+            let simulated_state = self.state.clone();
+            move || playout(simulated_state, player)
+        });
+        pool.run_all(jobs).into_iter().sum()
+    }
+
+    fn update(&self, util: Util, n: usize) {
+        // println!("Updating node with prev_action: {:?}", self.prev_action);
+        self.n.fetch_add(n, Ordering::Relaxed);
+        self.add_u(util);
+    }
+
+    fn add_u(&self, util: Util) {
+        let _ = self
+            .u
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |u| {
+                Some((f64::from_bits(u) + util as f64).to_bits())
+            });
+    }
+
+    /// Count a lost game for mover so that concurrent searches spread over different paths
+    fn add_virtual_loss(&self) {
+        self.n.fetch_add(VIRTUAL_LOSS, Ordering::Relaxed);
+    }
+
+    fn remove_virtual_loss(&self) {
+        self.n.fetch_sub(VIRTUAL_LOSS, Ordering::Relaxed);
+    }
+}
+
+/// When to stop searching, the search stops as soon as any of the set limits is reached
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SearchLimits {
+    pub time: Option<Duration>,
+    pub iterations: Option<usize>,
+}
+impl SearchLimits {
+    pub fn time(time: Duration) -> SearchLimits {
+        SearchLimits {
+            time: Some(time),
+            iterations: None,
+        }
+    }
+
+    pub fn iterations(iterations: usize) -> SearchLimits {
+        SearchLimits {
+            time: None,
+            iterations: Some(iterations),
+        }
+    }
+
+    fn reached(&self, start_time: Instant, iterations: usize) -> bool {
+        let time_up = self.time.is_some_and(|t| start_time.elapsed() >= t);
+        let iterations_done = self.iterations.is_some_and(|n| iterations >= n);
+        time_up || iterations_done || (self.time.is_none() && self.iterations.is_none())
+    }
+}
+
+/// Statistics of one move at the root of the tree
+#[derive(Clone, Copy, Debug)]
+pub struct MoveStats {
+    pub m: Move,
+    pub visits: usize,
+    /// Average utility of the move for the player to move at the root
+    pub value: Util,
+}
+
+#[derive(Clone, Debug)]
+pub struct SearchStats {
+    pub iterations: usize,
+    pub playouts: usize,
+    pub elapsed: Duration,
+    /// Nodes shared with another path through the tree instead of made, always 0 without
+    /// transpositions
+    pub transpositions: usize,
+    /// Root moves, most visited first, only the one starting the win when a forced win was found
+    pub moves: Vec<MoveStats>,
+}
+impl SearchStats {
+    pub fn playouts_per_second(&self) -> f32 {
+        self.playouts as f32 / self.elapsed.as_secs_f32()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SearchResult {
+    /// `None` when there are no moves left to play
+    pub best_move: Option<Move>,
+    /// Win by continuous fours found before searching, the best move starts it and the tree
+    /// isn't searched
+    pub vcf: Option<Vec<Move>>,
+    /// Win by continuous threats, open threes included, found before searching when there is no
+    /// win by fours, the best move starts it and the tree isn't searched
+    pub vct: Option<Vec<Move>>,
+    pub stats: SearchStats,
+}
+
+/// How the search spreads work over the threads
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Parallelism {
+    /// One tree walk at a time, every thread runs a playout from the same leaf
+    #[default]
+    Leaf,
+    /// Every thread walks the shared tree on its own, virtual losses keep them apart
+    Tree,
+    /// Every thread grows its own tree with its own random seed, root statistics are merged at
+    /// the end
+    Root,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SearchConfig {
+    /// Number of worker threads, with leaf parallelism each iteration runs one playout per thread
+    pub threads: usize,
+    pub parallelism: Parallelism,
+    /// Share the node of a position between all the move orders reaching it, searching a DAG
+    /// instead of a tree
+    pub transpositions: bool,
+    /// Most positions kept in the transposition table, split between the private trees with root
+    /// parallelism. Positions past it are searched as in the plain tree
+    pub table_capacity: usize,
+    /// Most fours of the win by continuous fours looked for before searching, 0 to skip it
+    pub vcf_fours: usize,
+    /// Most fours and threes of the win by continuous threats looked for before searching, after
+    /// the one by fours, 0 to skip it. With a time limit the two take at most a quarter of it
+    pub vct_threats: usize,
+}
+impl Default for SearchConfig {
+    fn default() -> Self {
+        SearchConfig {
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            parallelism: Parallelism::default(),
+            transpositions: true,
+            table_capacity: 1 << 20,
+            vcf_fours: 12,
+            vct_threats: 3,
+        }
+    }
+}
+
+pub struct SearchTree<G: Grid = ArrayGrid> {
+    root_node_ptr: Arc<Node<G>>,
+    pool: ThreadPool,
+    config: SearchConfig,
+    table: Option<Arc<TranspositionTable<G>>>,
+    previous_roots: VecDeque<Arc<Node<G>>>,
+    /// Position as played, the root may have been reached by another move order so `undo_move`
+    /// takes moves back from this one
+    game: Board<G>,
+}
+impl<G: Grid> SearchTree<G> {
+    pub fn monte_carlo(&mut self, time_limit: f32) -> Move {
+        let result = self.search(SearchLimits::time(Duration::from_secs_f32(time_limit)));
+        if let Some(line) = &result.vcf {
+            eprintln!("Winning by fours: {line:?}");
+        }
+        if let Some(line) = &result.vct {
+            eprintln!("Winning by threats: {line:?}");
+        }
+        eprintln!(
+            "Games simulated: {} ({:.0}/s)",
+            result.stats.playouts,
+            result.stats.playouts_per_second()
+        );
+        result.best_move.expect("no moves to search")
+    }
+
+    pub fn search(&mut self, limits: SearchLimits) -> SearchResult {
+        let start_time = Instant::now();
+        let deadline = limits
+            .time
+            .map(|time| start_time + time / FORCED_WIN_TIME_SHARE);
+        let vcf = self.vcf(deadline);
+        let vct = if vcf.is_none() {
+            self.vct(deadline)
+        } else {
+            None
+        };
+        if vcf.is_some() || vct.is_some() {
+            let best_move = vcf
+                .as_ref()
+                .or(vct.as_ref())
+                .and_then(|line| line.first().copied());
+            // the move starting the forced win wins for sure
+            let moves = best_move
+                .map(|m| MoveStats {
+                    m,
+                    visits: 0,
+                    value: 1.0,
+                })
+                .into_iter()
+                .collect();
+            return SearchResult {
+                best_move,
+                vcf,
+                vct,
+                stats: SearchStats {
+                    iterations: 0,
+                    playouts: 0,
+                    elapsed: start_time.elapsed(),
+                    transpositions: 0,
+                    moves,
+                },
+            };
+        }
+        let hits_before = self.hits();
+
+        let (iterations, game_simulated, worker_hits) = match self.config.parallelism {
+            Parallelism::Leaf => self.leaf_parallel_search(limits, start_time),
+            Parallelism::Tree => self.tree_parallel_search(limits, start_time),
+            Parallelism::Root => self.root_parallel_search(limits, start_time),
+        };
+
+        let root_node = &self.root_node_ptr;
+        if root_node.is_leaf() {
+            root_node.expand(self.table.as_deref());
+        }
+        let mut moves: Vec<_> = root_node
+            .children
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(m, child)| {
+                let n = child.n();
+                MoveStats {
+                    m: *m,
+                    visits: n,
+                    value: if n == 0 { 0.0 } else { child.u() / n as f32 },
+                }
+            })
+            .collect();
+        moves.sort_by_key(|stats| Reverse(stats.visits));
+        let best_move = match moves.first() {
+            Some(best) if best.visits > 0 => Some(best.m),
+            Some(_) => Some(moves[fastrand::usize(..moves.len())].m),
+            None => None,
+        };
+
+        SearchResult {
+            best_move,
+            vcf: None,
+            vct: None,
+            stats: SearchStats {
+                iterations,
+                playouts: game_simulated,
+                elapsed: start_time.elapsed(),
+                transpositions: self.hits() - hits_before + worker_hits,
+                moves,
+            },
+        }
+    }
+
+    /// Win by continuous fours for the player to move at the root, if the config looks for one,
+    /// given up at deadline
+    fn vcf(&self, deadline: Option<Instant>) -> Option<Vec<Move>> {
+        if self.config.vcf_fours == 0 {
+            return None;
+        }
+        let mut board = self.root_node_ptr.state.clone();
+        match deadline {
+            Some(deadline) => board.vcf_until(self.config.vcf_fours, deadline),
+            None => board.vcf(self.config.vcf_fours),
+        }
+    }
+
+    /// Win by continuous threats for the player to move at the root, if the config looks for one,
+    /// given up at deadline
+    fn vct(&self, deadline: Option<Instant>) -> Option<Vec<Move>> {
+        if self.config.vct_threats == 0 {
+            return None;
+        }
+        let mut board = self.root_node_ptr.state.clone();
+        match deadline {
+            Some(deadline) => board.vct_until(self.config.vct_threats, deadline),
+            None => board.vct(self.config.vct_threats),
+        }
+    }
+
+    /// Walk the tree from the calling thread, running the playouts of every leaf on the pool
+    fn leaf_parallel_search(
+        &self,
+        limits: SearchLimits,
+        start_time: Instant,
+    ) -> (usize, usize, usize) {
+        let num_playouts = self.pool.size();
+        let table = self.table.as_deref();
+        let mut iterations = 0;
+        while !limits.reached(start_time, iterations) {
+            SearchTree::iterate(&self.root_node_ptr, table, Some(&self.pool), false);
+            iterations += 1;
+        }
+        (iterations, iterations * num_playouts, 0)
+    }
+
+    /// Let every worker of the pool walk the tree independently until the limits are reached
+    fn tree_parallel_search(
+        &self,
+        limits: SearchLimits,
+        start_time: Instant,
+    ) -> (usize, usize, usize) {
+        let iterations = Arc::new(AtomicUsize::new(0));
+        let jobs = (0..self.pool.size()).map(|_| {
+            let root_node_ptr = self.root_node_ptr.clone();
+            let table = self.table.clone();
+            let iterations = iterations.clone();
+            move || {
+                let mut playouts = 0;
+                // claim an iteration before running it so the iteration limit isn't overshot
+                while !limits.reached(start_time, iterations.fetch_add(1, Ordering::Relaxed)) {
+                    playouts += SearchTree::iterate(&root_node_ptr, table.as_deref(), None, true);
+                }
+                iterations.fetch_sub(1, Ordering::Relaxed);
+                playouts
+            }
+        });
+        let playouts = self.pool.run_all(jobs).into_iter().sum();
+        (iterations.load(Ordering::Relaxed), playouts, 0)
+    }
+
+    /// Let every worker of the pool search a private tree, then add up the statistics of their
+    /// root moves into the root of this tree
+    fn root_parallel_search(
+        &self,
+        limits: SearchLimits,
+        start_time: Instant,
+    ) -> (usize, usize, usize) {
+        let iterations = Arc::new(AtomicUsize::new(0));
+        let base_seed = fastrand::u64(..);
+        let table_capacity = self.config.table_capacity / self.pool.size();
+        let jobs = (0..self.pool.size() as u64).map(|i| {
+            let init_state = self.root_node_ptr.state.clone();
+            let table = self
+                .config
+                .transpositions
+                .then(|| TranspositionTable::new(table_capacity));
+            let iterations = iterations.clone();
+            move || {
+                fastrand::seed(base_seed.wrapping_add(i));
+                let root_node_ptr = Arc::new(Node::new_root(init_state));
+                let mut playouts = 0;
+                while !limits.reached(start_time, iterations.fetch_add(1, Ordering::Relaxed)) {
+                    playouts += SearchTree::iterate(&root_node_ptr, table.as_ref(), None, false);
+                }
+                iterations.fetch_sub(1, Ordering::Relaxed);
+
+                let root_moves: Vec<_> = root_node_ptr
+                    .children
+                    .read()
+                    .unwrap()
+                    .iter()
+                    .map(|(m, child)| (*m, child.u(), child.n()))
+                    .collect();
+                let hits = table.map_or(0, |table| table.hits());
+                (playouts, root_moves, hits)
+            }
+        });
+        let results = self.pool.run_all(jobs);
+
+        let root_node = &self.root_node_ptr;
+        if root_node.is_leaf() {
+            root_node.expand(self.table.as_deref());
+        }
+        let children = root_node.children.read().unwrap();
+        let mut total_playouts = 0;
+        let mut total_hits = 0;
+        for (playouts, root_moves, hits) in results {
+            total_playouts += playouts;
+            total_hits += hits;
+            root_node.n.fetch_add(playouts, Ordering::Relaxed);
+            for (m, u, n) in root_moves {
+                if let Some((_, child)) = children.iter().find(|(child_m, _)| *child_m == m) {
+                    child.update(u, n);
+                }
+            }
+        }
+        (
+            iterations.load(Ordering::Relaxed),
+            total_playouts,
+            total_hits,
+        )
+    }
+
+    /// Run one selection, expansion, simulation and backpropagation pass, the playouts run on
+    /// pool if given or else a single one on the calling thread. Return the number of playouts
+    fn iterate(
+        root_node_ptr: &Arc<Node<G>>,
+        table: Option<&TranspositionTable<G>>,
+        pool: Option<&ThreadPool>,
+        virtual_loss: bool,
+    ) -> usize {
+        let mut path = SearchTree::select(root_node_ptr, virtual_loss);
+        // println!("path len {}", path.len());
+        let leaf = path.last().expect("path is empty").clone();
+        let visited = leaf.n() > if virtual_loss { VIRTUAL_LOSS } else { 0 };
+        if visited && !leaf.terminal {
+            if let Some(child) = leaf.expand(table) {
+                if virtual_loss {
+                    child.add_virtual_loss();
+                }
+                path.push(child);
+            }
+        }
+
+        let node_to_simulate = path.last().unwrap();
+        let player = node_to_simulate.mover;
+        let (util, n) = match pool {
+            Some(pool) => (node_to_simulate.simulate(pool, pool.size()), pool.size()),
+            None => (playout(node_to_simulate.state.clone(), player), 1),
+        };
+        if virtual_loss {
+            for node in path.iter() {
+                node.remove_virtual_loss();
+            }
+        }
+        SearchTree::back_propagation(path, player, util, n);
+        n
+    }
+
+    /// Board at the root of the tree, with the moves in the order they were played
+    pub fn board(&self) -> Board<G> {
+        self.game.clone()
+    }
+
+    pub fn new(init_state: Board<G>) -> SearchTree<G> {
+        SearchTree::with_config(init_state, SearchConfig::default())
+    }
+
+    pub fn with_config(init_state: Board<G>, config: SearchConfig) -> SearchTree<G> {
+        SearchTree {
+            root_node_ptr: Arc::new(Node::new_root(init_state.clone())),
+            pool: ThreadPool::new(config.threads),
+            config,
+            table: config
+                .transpositions
+                .then(|| Arc::new(TranspositionTable::new(config.table_capacity))),
+            previous_roots: VecDeque::new(),
+            game: init_state,
+        }
+    }
+
+    fn hits(&self) -> usize {
+        self.table.as_ref().map_or(0, |table| table.hits())
+    }
+
+    // Return the path from the root to the node that has no children yet
+    fn select(root_node_ptr: &Arc<Node<G>>, virtual_loss: bool) -> Vec<Arc<Node<G>>> {
+        let mut node = root_node_ptr.clone();
+        let mut path = Vec::new();
+        loop {
+            if virtual_loss {
+                node.add_virtual_loss();
+            }
+            path.push(node.clone());
+
+            let next = {
+                let children = node.children.read().unwrap();
+                if children.is_empty() {
+                    return path;
+                }
+
+                // calculate children's ucb1
+                let node_n = node.n();
+                let mut max_ucb1 = f32::MIN;
+                let mut max_idx = None;
+                for (i, (_, child)) in children.iter().enumerate() {
+                    let child_n = child.n();
+                    let mut ucb1 = if child_n == 0 {
+                        f32::INFINITY
+                    } else {
+                        child.u() / child_n as f32
+                            + C * f32::sqrt(f32::ln(node_n as f32) / child_n as f32)
+                    };
+                    if fastrand::bool() && child.threats {
+                        ucb1 *= 1.0 + (fastrand::f32() * 0.25);
+                    }
+
+                    // println!(
+                    //     "ucb1 for node with prev_action: {:?} is {}",
+                    //     node.prev_action, ucb1
+                    // );
+
+                    if ucb1 > max_ucb1 {
+                        max_idx = Some(i);
+                        max_ucb1 = ucb1;
+                    }
+                }
+
+                children[max_idx.expect("Unable to find max ucb1 value")]
+                    .1
+                    .clone()
+            };
+            node = next;
+        }
+    }
+
+    /// `util` is the total utility of the n games for `player`, every node is credited from the
+    /// view of its own mover so that each player maximises their own payoff during selection
+    fn back_propagation(path: Vec<Arc<Node<G>>>, player: Player, util: Util, n: usize) {
+        for node in path {
+            if node.mover == player {
+                node.update(util, n);
+            } else {
+                node.update(n as Util - util, n);
+            }
+        }
+    }
+
+    pub fn apply_move(&mut self, m: Move) {
+        let root_node = &self.root_node_ptr;
+        if root_node.is_leaf() {
+            root_node.expand(self.table.as_deref());
+        }
+        let target_node = root_node
+            .children
+            .read()
+            .unwrap()
+            .iter()
+            .find(|(child_m, _)| *child_m == m)
+            .map(|(_, child)| child.clone());
+
+        let previous_root = self.root_node_ptr.clone();
+        self.game.place(m).unwrap();
+        match target_node {
+            None => {
+                // the position may still have been searched through another move order
+                let known = self.table.as_ref().and_then(|table| table.get(&self.game));
+                self.root_node_ptr = known.unwrap_or_else(|| {
+                    eprintln!("Creating new tree");
+                    Arc::new(Node::new_root(self.game.clone()))
+                });
+            }
+            Some(node_ptr) => {
+                self.root_node_ptr = node_ptr;
+            }
+        }
+        self.previous_roots.push_back(previous_root);
+        if self.previous_roots.len() > KEPT_ROOTS {
+            self.previous_roots.pop_front();
+        }
+    }
+
+    /// Step back to the position before the last move, keeping its statistics if it is one of the
+    /// last few roots. Return the move taken back, or `None` at the start of the game
+    pub fn undo_move(&mut self) -> Option<Move> {
+        let m = self.game.undo()?;
+        let previous_root = self
+            .previous_roots
+            .pop_back()
+            .filter(|root| root.state.same_position(&self.game))
+            .or_else(|| self.table.as_ref().and_then(|table| table.get(&self.game)));
+        self.root_node_ptr =
+            previous_root.unwrap_or_else(|| Arc::new(Node::new_root(self.game.clone())));
+        Some(m)
+    }
+}
+impl<G: Grid> Display for SearchTree<G> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut q = VecDeque::new();
+        q.push_back((self.root_node_ptr.clone(), None, None));
+        while !q.is_empty() {
+            let (node, parent_id, prev_action) = q.pop_front().unwrap();
+            if let Some(id) = parent_id {
+                write!(f, "parent: {id} ")?;
+            }
+            if let Some(m) = prev_action {
+                write!(f, "m: {:?} ", m)?;
+            }
+            let this_id: String = Uuid::new_v4().to_string().chars().take(4).collect();
+            writeln!(f, "n: {:<3} u: {:<3} id:{}", node.n(), node.u(), this_id)?;
+            for (m, child_ptr) in node.children.read().unwrap().iter() {
+                q.push_back((child_ptr.clone(), Some(this_id.clone()), Some(*m)));
+            }
+        }
+        writeln!(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{Node, Parallelism, SearchConfig, SearchLimits, SearchTree, TranspositionTable};
+    use crate::board::{BitGrid, Board, Move, Outcome, Player, Rule};
+
+    fn board_from_moves(width: usize, height: usize, moves: &[Move]) -> Board {
+        let mut board = Board::new(width, height);
+        for &m in moves {
+            board.place(m).unwrap();
+        }
+        board
+    }
+
+    fn search_all_modes(board: &Board, transpositions: bool) -> Vec<(Parallelism, Option<Move>)> {
+        [
+            (Parallelism::Leaf, 4, 3000),
+            (Parallelism::Tree, 4, 12000),
+            (Parallelism::Root, 2, 24000),
+        ]
+        .into_iter()
+        .map(|(parallelism, threads, iterations)| {
+            let config = SearchConfig {
+                threads,
+                parallelism,
+                transpositions,
+                ..SearchConfig::default()
+            };
+            let mut search_tree = SearchTree::with_config(board.clone(), config);
+            let result = search_tree.search(SearchLimits::iterations(iterations));
+            (parallelism, result.best_move)
+        })
+        .collect()
+    }
+
+    #[test]
+    fn test_blocks_split_four_over_double_four() {
+        // O threatens to fill (3, 4) for five, X playing (4, 6) would make a double four which
+        // wins most random playouts but loses straight away to O's five
+        let board = board_from_moves(
+            9,
+            9,
+            &[
+                [0, 6],
+                [1, 4],
+                [1, 6],
+                [2, 4],
+                [2, 6],
+                [4, 4],
+                [6, 6],
+                [5, 4],
+                [7, 6],
+                [8, 4],
+                [8, 6],
+                [0, 5],
+            ],
+        );
+        for (parallelism, best_move) in search_all_modes(&board, true) {
+            assert_eq!(best_move, Some([3, 4]), "{parallelism:?}");
+        }
+    }
+
+    #[test]
+    fn test_blocks_split_four_as_second_player() {
+        // same position with the colours swapped, O has to fill (3, 4) before X does
+        let board = board_from_moves(
+            9,
+            9,
+            &[
+                [1, 4],
+                [0, 6],
+                [2, 4],
+                [1, 6],
+                [4, 4],
+                [2, 6],
+                [5, 4],
+                [6, 6],
+                [8, 4],
+                [7, 6],
+                [0, 5],
+                [8, 6],
+                [8, 5],
+            ],
+        );
+        // also keeps the plain tree covered
+        for (parallelism, best_move) in search_all_modes(&board, false) {
+            assert_eq!(best_move, Some([3, 4]), "{parallelism:?}");
+        }
+    }
+
+    #[test]
+    fn test_connect6_completes_six() {
+        // X has an open four and two stones to play
+        let mut board = Board::with_rule(12, 12, Rule::Connect6);
+        for m in [
+            [3, 5],
+            [11, 0],
+            [11, 11],
+            [4, 5],
+            [5, 5],
+            [0, 0],
+            [0, 11],
+            [6, 5],
+            [9, 11],
+            [5, 0],
+            [6, 11],
+        ] {
+            board.place(m).unwrap();
+        }
+        let mut search_tree = SearchTree::new(board.clone());
+        let first = search_tree
+            .search(SearchLimits::iterations(2000))
+            .best_move
+            .unwrap();
+        assert!([[1, 5], [2, 5], [7, 5], [8, 5]].contains(&first));
+        board.place(first).unwrap();
+        search_tree.apply_move(first);
+        let second = search_tree
+            .search(SearchLimits::iterations(2000))
+            .best_move
+            .unwrap();
+        board.place(second).unwrap();
+        assert_eq!(board.utility(Player::X), Some(1.0));
+    }
+
+    #[test]
+    fn test_search_on_bit_grid() {
+        // X has four in a row against the edge and wins at (4, 0)
+        let board = board_from_moves(
+            9,
+            9,
+            &[
+                [0, 0],
+                [8, 8],
+                [1, 0],
+                [8, 6],
+                [2, 0],
+                [8, 4],
+                [3, 0],
+                [8, 2],
+            ],
+        );
+        let config = SearchConfig {
+            threads: 2,
+            vcf_fours: 0,
+            vct_threats: 0,
+            ..SearchConfig::default()
+        };
+        let mut search_tree = SearchTree::with_config(board.into_grid::<BitGrid>(), config);
+        let result = search_tree.search(SearchLimits::iterations(100));
+        assert_eq!(result.best_move, Some([4, 0]));
+        assert_eq!(result.stats.playouts, 200);
+        search_tree.apply_move([4, 0]);
+        assert_eq!(search_tree.board().result(), Some(Outcome::Win(Player::X)));
+    }
+
+    #[test]
+    fn test_search_stats() {
+        let board = board_from_moves(9, 9, &[[4, 4]]);
+        let config = SearchConfig {
+            threads: 3,
+            ..SearchConfig::default()
+        };
+        let mut search_tree = SearchTree::with_config(board, config);
+        let result = search_tree.search(SearchLimits::iterations(20));
+        assert_eq!(result.stats.iterations, 20);
+        assert_eq!(result.stats.playouts, 60);
+        assert!(result.stats.playouts_per_second() > 0.0);
+        let visits: usize = result.stats.moves.iter().map(|stats| stats.visits).sum();
+        assert_eq!(visits, 60 - 3); // first iteration simulates the root itself
+    }
+
+    #[test]
+    fn test_tree_parallel_stats() {
+        let board = board_from_moves(9, 9, &[[4, 4]]);
+        let config = SearchConfig {
+            threads: 4,
+            parallelism: Parallelism::Tree,
+            ..SearchConfig::default()
+        };
+        let mut search_tree = SearchTree::with_config(board, config);
+        let result = search_tree.search(SearchLimits::iterations(200));
+        assert_eq!(result.stats.iterations, 200);
+        assert_eq!(result.stats.playouts, 200);
+        // virtual losses are all taken back once the workers are done
+        let visits: usize = result.stats.moves.iter().map(|stats| stats.visits).sum();
+        assert_eq!(visits, 200 - 1);
+    }
+
+    #[test]
+    fn test_root_parallel_merges_stats() {
+        let board = board_from_moves(9, 9, &[[4, 4]]);
+        let config = SearchConfig {
+            threads: 4,
+            parallelism: Parallelism::Root,
+            ..SearchConfig::default()
+        };
+        let mut search_tree = SearchTree::with_config(board, config);
+        let result = search_tree.search(SearchLimits::iterations(200));
+        assert_eq!(result.stats.iterations, 200);
+        assert_eq!(result.stats.playouts, 200);
+        // every private tree spends its first iteration on its own root
+        let visits: usize = result.stats.moves.iter().map(|stats| stats.visits).sum();
+        assert!((200 - 4..200).contains(&visits));
+        // merged statistics stay in the tree for the next search
+        let result = search_tree.search(SearchLimits::iterations(100));
+        let visits: usize = result.stats.moves.iter().map(|stats| stats.visits).sum();
+        assert!((300 - 8..300).contains(&visits));
+    }
+
+    #[test]
+    fn test_undo_move() {
+        let board = board_from_moves(9, 9, &[[4, 4]]);
+        let config = SearchConfig {
+            threads: 1,
+            ..SearchConfig::default()
+        };
+        let mut search_tree = SearchTree::with_config(board, config);
+        let total_visits = |search_tree: &mut SearchTree| -> usize {
+            let result = search_tree.search(SearchLimits::iterations(0));
+            result.stats.moves.iter().map(|stats| stats.visits).sum()
+        };
+        let m = search_tree
+            .search(SearchLimits::iterations(200))
+            .best_move
+            .unwrap();
+        search_tree.apply_move(m);
+        search_tree.apply_move(search_tree.board().legal_moves()[0]);
+        search_tree.undo_move().unwrap();
+        assert_eq!(search_tree.undo_move(), Some(m));
+        // back at the searched root
+        assert_eq!(total_visits(&mut search_tree), 200 - 1);
+        assert_eq!(search_tree.undo_move(), Some([4, 4]));
+        assert_eq!(search_tree.board().history().count(), 0);
+        assert_eq!(search_tree.undo_move(), None);
+    }
+
+    #[test]
+    fn test_undo_move_after_transposition() {
+        let mut search_tree = SearchTree::new(Board::new(15, 15));
+        let first = [[7, 7], [8, 8], [8, 7], [7, 8]];
+        for m in first {
+            search_tree.apply_move(m);
+        }
+        while search_tree.undo_move().is_some() {}
+        // the same stones in another order reach the root of the first game
+        let second = [[8, 7], [7, 8], [7, 7], [8, 8]];
+        for m in second {
+            search_tree.apply_move(m);
+        }
+        assert!(search_tree.root_node_ptr.state.history().eq(first));
+        assert!(search_tree.board().history().eq(second));
+        assert_eq!(search_tree.undo_move(), Some([8, 8]));
+        let board = board_from_moves(15, 15, &second[..3]);
+        assert_eq!(search_tree.board().hash(), board.hash());
+        assert_eq!(search_tree.root_node_ptr.state.hash(), board.hash());
+        assert_eq!(search_tree.undo_move(), Some([7, 7]));
+    }
+
+    #[test]
+    fn test_transpositions() {
+        let board = board_from_moves(9, 9, &[[4, 4]]);
+        let search = |transpositions| {
+            let config = SearchConfig {
+                threads: 1,
+                transpositions,
+                ..SearchConfig::default()
+            };
+            let mut search_tree = SearchTree::with_config(board.clone(), config);
+            search_tree.search(SearchLimits::iterations(2000)).stats
+        };
+        assert_eq!(search(false).transpositions, 0);
+        let dag = search(true);
+        assert!(dag.transpositions > 0);
+        let visits: usize = dag.moves.iter().map(|stats| stats.visits).sum();
+        assert_eq!(visits, 2000 - 1);
+
+        // X [3, 4], O [5, 5], X [4, 3] and X [4, 3], O [5, 5], X [3, 4] reach the same node
+        let table = TranspositionTable::new(1 << 10);
+        let child = |node: &Node, m: Move, table: Option<&TranspositionTable>| {
+            node.expand(table);
+            let children = node.children.read().unwrap();
+            children
+                .iter()
+                .find(|(child_m, _)| *child_m == m)
+                .unwrap()
+                .1
+                .clone()
+        };
+        let walk = |moves: [Move; 3], table| {
+            let mut node = Arc::new(Node::new_root(board.clone()));
+            for m in moves {
+                node = child(&node, m, table);
+            }
+            node
+        };
+        let first = walk([[3, 4], [5, 5], [4, 3]], Some(&table));
+        let second = walk([[4, 3], [5, 5], [3, 4]], Some(&table));
+        assert!(Arc::ptr_eq(&first, &second));
+        let plain = walk([[4, 3], [5, 5], [3, 4]], None);
+        assert!(!Arc::ptr_eq(&first, &plain));
+    }
+
+    #[test]
+    fn test_forced_win_shortcut() {
+        let search = |board: &Board, vcf_fours, vct_threats| {
+            let config = SearchConfig {
+                threads: 1,
+                vcf_fours,
+                vct_threats,
+                ..SearchConfig::default()
+            };
+            SearchTree::with_config(board.clone(), config).search(SearchLimits::iterations(50))
+        };
+        // X wins by fours, see the VCF tests of the board
+        let board: Board = "15/15/15/15/15/oxxx11/6xxxo5/5x9/5x9/5o9/15/15/2o4o7/15/o4o4o4 x"
+            .parse()
+            .unwrap();
+        let result = search(&board, 12, 0);
+        let line = result.vcf.unwrap();
+        assert_eq!(result.vct, None);
+        assert_eq!(result.best_move, Some(line[0]));
+        assert_eq!(result.stats.iterations, 0);
+        assert_eq!(result.stats.moves.len(), 1);
+        assert_eq!(result.stats.moves[0].m, line[0]);
+        assert_eq!(result.stats.moves[0].value, 1.0);
+        assert_eq!(board.clone().vcf(12), Some(line));
+
+        // and by two threes at once, but not by fours
+        let board: Board = "14o/15/14o/15/15/7x7/7x7/4xx9/15/15/15/15/o14/15/o14 x"
+            .parse()
+            .unwrap();
+        let result = search(&board, 12, 3);
+        assert_eq!(result.vcf, None);
+        assert_eq!(result.vct, Some(vec![[7, 7]]));
+        assert_eq!(result.best_move, Some([7, 7]));
+
+        let result = search(&board, 12, 0);
+        assert_eq!(result.vct, None);
+        assert_eq!(result.stats.iterations, 50);
+    }
+}
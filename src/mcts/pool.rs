@@ -0,0 +1,105 @@
+use std::{
+    sync::{mpsc, Arc, Mutex},
+    thread::{self, JoinHandle},
+};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// Fixed set of worker threads that live as long as the pool and run submitted jobs
+pub struct ThreadPool {
+    workers: Vec<JoinHandle<()>>,
+    sender: Option<mpsc::Sender<Job>>,
+}
+impl ThreadPool {
+    pub fn new(size: usize) -> ThreadPool {
+        assert!(size > 0, "thread pool needs at least one thread");
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = (0..size)
+            .map(|_| {
+                let receiver = receiver.clone();
+                thread::spawn(move || loop {
+                    // the lock is released before running the job so other workers can take jobs
+                    let job = receiver.lock().unwrap().recv();
+                    match job {
+                        Ok(job) => job(),
+                        Err(_) => break, // pool was dropped
+                    }
+                })
+            })
+            .collect();
+        ThreadPool {
+            workers,
+            sender: Some(sender),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Run every job on the pool and wait for all of them, results come back in completion order
+    pub fn run_all<T, F>(&self, jobs: impl IntoIterator<Item = F>) -> Vec<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel();
+        let mut num_jobs = 0;
+        for job in jobs {
+            let tx = tx.clone();
+            self.execute(move || {
+                // the receiver only goes away if the caller panicked
+                let _ = tx.send(job());
+            });
+            num_jobs += 1;
+        }
+        drop(tx);
+        let results: Vec<T> = rx.iter().collect();
+        assert_eq!(results.len(), num_jobs, "a job panicked in the thread pool");
+        results
+    }
+
+    fn execute<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.sender
+            .as_ref()
+            .expect("thread pool is shut down")
+            .send(Box::new(f))
+            .expect("all workers of the thread pool died");
+    }
+}
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        drop(self.sender.take());
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, thread};
+
+    use super::ThreadPool;
+
+    #[test]
+    fn test_run_all() {
+        let pool = ThreadPool::new(3);
+        let mut threads = HashSet::new();
+        for _ in 0..5 {
+            let mut results =
+                pool.run_all((0..20).map(|i| move || (i * 2, thread::current().id())));
+            results.sort_by_key(|&(r, _)| r);
+            let values: Vec<_> = results.iter().map(|&(r, _)| r).collect();
+            assert_eq!(values, (0..20).map(|i| i * 2).collect::<Vec<_>>());
+            threads.extend(results.iter().map(|&(_, id)| id));
+        }
+        // the same workers are reused for every batch
+        assert!(threads.len() <= pool.size());
+        assert!(!threads.contains(&thread::current().id()));
+    }
+}
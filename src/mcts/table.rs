@@ -0,0 +1,161 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, Weak,
+    },
+};
+
+use super::Node;
+use crate::board::{ArrayGrid, Board, Grid, Player};
+
+/// Part of the capacity missed while the table is full between two sweeps for dropped nodes, so
+/// that a sweep's cost is spread over the misses before it
+const SWEEP_SHARE: usize = 8;
+
+/// Nodes of the search by position hash, so that a position reached by different move orders is
+/// searched once and its statistics are shared. The table only holds weak references, nodes live
+/// as long as the tree keeps them
+pub struct TranspositionTable<G: Grid = ArrayGrid> {
+    entries: Mutex<Entries<G>>,
+    capacity: usize,
+    hits: AtomicUsize,
+}
+
+struct Entries<G: Grid> {
+    nodes: HashMap<u64, Weak<Node<G>>>,
+    /// Misses of a full table left before sweeping it again
+    misses_to_sweep: usize,
+}
+
+impl<G: Grid> TranspositionTable<G> {
+    /// Table of at most capacity positions, once it is full new positions get nodes of their own
+    pub fn new(capacity: usize) -> TranspositionTable<G> {
+        TranspositionTable {
+            entries: Mutex::new(Entries {
+                nodes: HashMap::new(),
+                misses_to_sweep: 0,
+            }),
+            capacity,
+            hits: AtomicUsize::new(0),
+        }
+    }
+
+    /// Node of the position of board, if the tree still has one
+    pub fn get(&self, board: &Board<G>) -> Option<Arc<Node<G>>> {
+        let entries = self.entries.lock().unwrap();
+        Self::find(&entries, board)
+    }
+
+    /// Node of the position of state, or a new one with mover the last to play, stored if there
+    /// is room
+    pub fn get_or_insert(&self, state: Board<G>, mover: Player) -> Arc<Node<G>> {
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(node) = Self::find(&entries, &state) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return node;
+        }
+        let hash = state.hash();
+        let node = Arc::new(Node::new(state, mover));
+        if entries.nodes.len() >= self.capacity {
+            if entries.misses_to_sweep == 0 {
+                // make room from nodes the tree has dropped since, e.g. after moving the root
+                entries.nodes.retain(|_, node| node.strong_count() > 0);
+                entries.misses_to_sweep = self.capacity / SWEEP_SHARE;
+            } else {
+                entries.misses_to_sweep -= 1;
+            }
+        }
+        // a node left behind by a colliding position is kept
+        let is_taken = entries
+            .nodes
+            .get(&hash)
+            .is_some_and(|node| node.strong_count() > 0);
+        if entries.nodes.len() < self.capacity && !is_taken {
+            entries.nodes.insert(hash, Arc::downgrade(&node));
+        }
+        node
+    }
+
+    /// Live node stored under the hash of board, if it is of the same position and not of
+    /// another one colliding with it
+    fn find(entries: &Entries<G>, board: &Board<G>) -> Option<Arc<Node<G>>> {
+        entries
+            .nodes
+            .get(&board.hash())
+            .and_then(Weak::upgrade)
+            .filter(|node| node.state.same_position(board))
+    }
+
+    /// Number of times a node was shared instead of made
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::TranspositionTable;
+    use crate::board::{Board, Player};
+    use crate::mcts::Node;
+
+    /// Position after X plays the i-th point of a 9x9 board
+    fn position(i: usize) -> Board {
+        let mut board = Board::new(9, 9);
+        board.place([i % 9, i / 9]).unwrap();
+        board
+    }
+
+    #[test]
+    fn test_capacity() {
+        let table = TranspositionTable::new(2);
+        let insert = |i| table.get_or_insert(position(i), Player::X);
+        let first = insert(1);
+        let second = insert(2);
+        assert!(Arc::ptr_eq(&insert(1), &first));
+        assert_eq!(table.hits(), 1);
+        // full, the third position isn't kept
+        let third = insert(3);
+        assert!(!Arc::ptr_eq(&insert(3), &third));
+        assert!(table.get(&position(3)).is_none());
+        // dropped nodes make room
+        drop(second);
+        let third = insert(3);
+        assert!(Arc::ptr_eq(&table.get(&position(3)).unwrap(), &third));
+        assert!(table.get(&position(2)).is_none());
+        assert!(Arc::ptr_eq(&table.get(&position(1)).unwrap(), &first));
+    }
+
+    #[test]
+    fn test_sweeps_in_batches() {
+        let table = TranspositionTable::new(16);
+        let insert = |i| table.get_or_insert(position(i), Player::X);
+        let mut kept: Vec<_> = (0..16).map(insert).collect();
+        // sweeping the full table finds nothing to drop, the next sweep waits for 2 more misses
+        insert(16);
+        kept.remove(0);
+        kept.extend([insert(17), insert(18)]);
+        assert!(table.get(&position(17)).is_none());
+        assert!(table.get(&position(18)).is_none());
+        let node = insert(19);
+        assert!(Arc::ptr_eq(&table.get(&position(19)).unwrap(), &node));
+    }
+
+    #[test]
+    fn test_collision() {
+        let table = TranspositionTable::new(16);
+        let (first, second) = (position(1), position(2));
+        // the node of the first position stored under the hash of the second
+        let node = Arc::new(Node::new(first.clone(), Player::X));
+        let mut entries = table.entries.lock().unwrap();
+        entries.nodes.insert(second.hash(), Arc::downgrade(&node));
+        drop(entries);
+        assert!(table.get(&second).is_none());
+        let other = table.get_or_insert(second.clone(), Player::X);
+        assert!(!Arc::ptr_eq(&other, &node));
+        assert!(other.state.same_position(&second));
+        assert_eq!(table.hits(), 0);
+    }
+}
@@ -0,0 +1,304 @@
+//! Swap and Swap2 openings, which balance the first player's advantage by letting the opponent
+//! pick colours after seeing the opening stones.
+
+use std::fmt::Display;
+
+use crate::board::{Board, Move, PlacingError, Player, Util};
+use crate::mcts::{SearchLimits, SearchTree};
+
+/// Stones of the opening, black, white then black
+pub const OPENING_STONES: usize = 3;
+/// Openings tried by the engine when looking for a balanced one
+const CANDIDATES: usize = 8;
+/// Candidate stones are at most this far from the centre
+const CANDIDATE_RADIUS: usize = 3;
+/// How far from even a position has to be for the Swap2 chooser to take a colour straight away
+const CLEAR_ADVANTAGE: Util = 0.1;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OpeningRule {
+    /// The opener places three stones, the chooser picks a colour
+    Swap,
+    /// Like Swap, but the chooser may instead add a white and a black stone and leave the colour
+    /// choice to the opener
+    Swap2,
+}
+
+/// The two players of an opening
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Seat {
+    Opener,
+    Chooser,
+}
+impl Seat {
+    pub fn other(&self) -> Seat {
+        match self {
+            Seat::Opener => Seat::Chooser,
+            Seat::Chooser => Seat::Opener,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SwapChoice {
+    /// Play this colour, the other seat gets the other one
+    Colour(Player),
+    /// Swap2 only, place a white then a black stone and let the opener choose
+    AddStones([Move; 2]),
+}
+
+#[derive(Debug)]
+pub enum OpeningError {
+    Placing(PlacingError),
+    /// The action doesn't fit the current stage of the opening
+    Unexpected,
+}
+impl Display for OpeningError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OpeningError::Placing(e) => e.fmt(f),
+            OpeningError::Unexpected => f.write_str("Not expected at this point of the opening"),
+        }
+    }
+}
+impl std::error::Error for OpeningError {}
+impl From<PlacingError> for OpeningError {
+    fn from(e: PlacingError) -> Self {
+        OpeningError::Placing(e)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Stage {
+    Placing,
+    Choosing(Seat),
+    Decided { opener: Player },
+}
+
+/// An opening being played out, from the opening stones to the colour assignment
+pub struct Opening {
+    rule: OpeningRule,
+    board: Board,
+    stage: Stage,
+}
+impl Opening {
+    pub fn new(rule: OpeningRule, board: Board) -> Opening {
+        Opening {
+            rule,
+            board,
+            stage: Stage::Placing,
+        }
+    }
+
+    pub fn rule(&self) -> OpeningRule {
+        self.rule
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    /// Board once the colours are decided, the game continues from it
+    pub fn into_board(self) -> Board {
+        self.board
+    }
+
+    /// Place the opener's stones, black, white then black
+    pub fn place_opening(&mut self, stones: [Move; OPENING_STONES]) -> Result<(), OpeningError> {
+        if self.stage != Stage::Placing {
+            return Err(OpeningError::Unexpected);
+        }
+        self.board = place_all(&self.board, &stones)?;
+        self.stage = Stage::Choosing(Seat::Chooser);
+        Ok(())
+    }
+
+    /// Seat that has to choose next, `None` before the opening is placed or once colours are
+    /// decided
+    pub fn to_choose(&self) -> Option<Seat> {
+        match self.stage {
+            Stage::Choosing(seat) => Some(seat),
+            _ => None,
+        }
+    }
+
+    /// Make the choice for the seat returned by `to_choose`
+    pub fn choose(&mut self, choice: SwapChoice) -> Result<(), OpeningError> {
+        let Stage::Choosing(seat) = self.stage else {
+            return Err(OpeningError::Unexpected);
+        };
+        match choice {
+            SwapChoice::Colour(colour) => {
+                let opener = match seat {
+                    Seat::Opener => colour,
+                    Seat::Chooser => colour.opponent(),
+                };
+                self.stage = Stage::Decided { opener };
+            }
+            SwapChoice::AddStones(stones) => {
+                if self.rule != OpeningRule::Swap2 || seat != Seat::Chooser {
+                    return Err(OpeningError::Unexpected);
+                }
+                self.board = place_all(&self.board, &stones)?;
+                self.stage = Stage::Choosing(Seat::Opener);
+            }
+        }
+        Ok(())
+    }
+
+    /// Colour played by seat, `None` until it is decided
+    pub fn colour(&self, seat: Seat) -> Option<Player> {
+        match (self.stage, seat) {
+            (Stage::Decided { opener }, Seat::Opener) => Some(opener),
+            (Stage::Decided { opener }, Seat::Chooser) => Some(opener.opponent()),
+            _ => None,
+        }
+    }
+
+    /// Engine's choice for the seat to choose, taking the colour the search favours, or under
+    /// Swap2 adding stones when neither colour is clearly better
+    pub fn decide(&self, limits: SearchLimits) -> Option<SwapChoice> {
+        let seat = self.to_choose()?;
+        let value = evaluate(&self.board, limits);
+        let to_move = self.board.player;
+        let better = if value >= 0.5 {
+            to_move
+        } else {
+            to_move.opponent()
+        };
+        let may_add = self.rule == OpeningRule::Swap2 && seat == Seat::Chooser;
+        if may_add && (value - 0.5).abs() < CLEAR_ADVANTAGE {
+            let stones = balanced_stones(&self.board, 2, limits);
+            return Some(SwapChoice::AddStones([stones[0], stones[1]]));
+        }
+        Some(SwapChoice::Colour(better))
+    }
+}
+
+/// Opening stones proposed by the engine, the candidate whose evaluation is closest to even so
+/// that the chooser gains nothing from either colour
+pub fn propose_opening(board: &Board, limits: SearchLimits) -> [Move; OPENING_STONES] {
+    let stones = balanced_stones(board, OPENING_STONES, limits);
+    [stones[0], stones[1], stones[2]]
+}
+
+/// Place stones one after another on a copy of board
+fn place_all(board: &Board, stones: &[Move]) -> Result<Board, PlacingError> {
+    let mut board = board.clone();
+    for &m in stones {
+        board.place(m)?;
+    }
+    Ok(board)
+}
+
+/// Expected utility of the position for the player to move, from searching it with limits
+fn evaluate(board: &Board, limits: SearchLimits) -> Util {
+    let result = SearchTree::new(board.clone()).search(limits);
+    result
+        .stats
+        .moves
+        .iter()
+        .find(|stats| Some(stats.m) == result.best_move)
+        .map_or(0.5, |stats| stats.value)
+}
+
+/// n stones near the centre leaving the most even position out of a few random candidates, each
+/// searched with limits
+fn balanced_stones(board: &Board, n: usize, limits: SearchLimits) -> Vec<Move> {
+    let [cx, cy] = [board.width() / 2, board.height() / 2];
+    let near_centre =
+        |&[x, y]: &Move| x.abs_diff(cx) <= CANDIDATE_RADIUS && y.abs_diff(cy) <= CANDIDATE_RADIUS;
+
+    let mut best: Option<(Util, Vec<Move>)> = None;
+    for _ in 0..CANDIDATES {
+        let mut candidate = board.clone();
+        let mut stones = Vec::new();
+        for _ in 0..n {
+            // a restriction may keep stones away from the centre
+            let legal_moves = candidate.legal_moves();
+            let mut moves: Vec<Move> = legal_moves.iter().copied().filter(near_centre).collect();
+            if moves.is_empty() {
+                moves = legal_moves;
+            }
+            let m = moves[fastrand::usize(..moves.len())];
+            candidate.place(m).expect("legal moves can be placed");
+            stones.push(m);
+        }
+        if candidate.result().is_some() {
+            continue;
+        }
+        let imbalance = (evaluate(&candidate, limits) - 0.5).abs();
+        if best.as_ref().is_none_or(|(b, _)| imbalance < *b) {
+            best = Some((imbalance, stones));
+        }
+    }
+    best.expect("every candidate ended the game").1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Opening, OpeningError, OpeningRule, Seat, SwapChoice};
+    use crate::board::{Board, Player};
+    use crate::mcts::SearchLimits;
+
+    #[test]
+    fn test_swap() {
+        let mut opening = Opening::new(OpeningRule::Swap, Board::new(15, 15));
+        assert_eq!(opening.to_choose(), None);
+        opening.place_opening([[7, 7], [8, 7], [7, 8]]).unwrap();
+        assert_eq!(opening.to_choose(), Some(Seat::Chooser));
+        assert!(matches!(
+            opening.choose(SwapChoice::AddStones([[0, 0], [1, 1]])),
+            Err(OpeningError::Unexpected)
+        ));
+        opening.choose(SwapChoice::Colour(Player::O)).unwrap();
+        assert_eq!(opening.to_choose(), None);
+        assert_eq!(opening.colour(Seat::Opener), Some(Player::X));
+        assert_eq!(opening.colour(Seat::Chooser), Some(Player::O));
+        assert_eq!(opening.board().player, Player::O);
+    }
+
+    #[test]
+    fn test_swap2_add_stones() {
+        let mut opening = Opening::new(OpeningRule::Swap2, Board::new(15, 15));
+        opening.place_opening([[7, 7], [8, 7], [7, 8]]).unwrap();
+        assert!(matches!(
+            opening.choose(SwapChoice::AddStones([[7, 7], [9, 9]])),
+            Err(OpeningError::Placing(_))
+        ));
+        opening
+            .choose(SwapChoice::AddStones([[8, 8], [9, 9]]))
+            .unwrap();
+        assert_eq!(opening.to_choose(), Some(Seat::Opener));
+        opening.choose(SwapChoice::Colour(Player::O)).unwrap();
+        assert_eq!(opening.colour(Seat::Opener), Some(Player::O));
+        assert_eq!(opening.colour(Seat::Chooser), Some(Player::X));
+        assert_eq!(opening.board().player, Player::O);
+    }
+
+    #[test]
+    fn test_evaluate_forced_win() {
+        // X wins by fours, see the VCF tests of the board
+        let board: Board = "15/15/15/15/15/oxxx11/6xxxo5/5x9/5x9/5o9/15/15/2o4o7/15/o4o4o4 x"
+            .parse()
+            .unwrap();
+        assert_eq!(super::evaluate(&board, SearchLimits::iterations(10)), 1.0);
+    }
+
+    #[test]
+    fn test_engine_opening() {
+        let limits = SearchLimits::iterations(100);
+        let mut opening = Opening::new(OpeningRule::Swap2, Board::new(15, 15));
+        let stones = super::propose_opening(opening.board(), limits);
+        opening.place_opening(stones).unwrap();
+        let choice = opening.decide(limits).unwrap();
+        opening.choose(choice).unwrap();
+        if let Some(seat) = opening.to_choose() {
+            assert_eq!(seat, Seat::Opener);
+            let choice = opening.decide(limits).unwrap();
+            assert!(matches!(choice, SwapChoice::Colour(_)));
+            opening.choose(choice).unwrap();
+        }
+        assert!(opening.colour(Seat::Opener).is_some());
+    }
+}
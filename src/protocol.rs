@@ -0,0 +1,367 @@
+use std::io::{self, BufRead, Write};
+
+use crate::board::{Board, Rule};
+use crate::mcts::SearchTree;
+
+const MIN_SIZE: usize = 5;
+const DEFAULT_TIMEOUT_TURN: u64 = 5000; // ms
+const TIME_MARGIN: u64 = 300; // ms kept back from every budget
+const EXPECTED_MOVES_LEFT: u64 = 25;
+// bits of INFO rule
+const RULE_EXACT_FIVE: u32 = 1;
+const RULE_RENJU: u32 = 4;
+const RULE_CARO: u32 = 8;
+
+/// Settings received through the INFO command, times are in milliseconds
+struct Info {
+    timeout_turn: u64,
+    timeout_match: u64,
+    time_left: Option<u64>,
+    rule: Rule,
+}
+impl Default for Info {
+    fn default() -> Self {
+        Info {
+            timeout_turn: DEFAULT_TIMEOUT_TURN,
+            timeout_match: 0,
+            time_left: None,
+            rule: Rule::default(),
+        }
+    }
+}
+
+/// A brain speaking the Gomocup (Piskvork) protocol
+pub struct Brain {
+    size: usize,
+    board: Option<Board>,
+    search_tree: Option<SearchTree>,
+    info: Info,
+}
+impl Default for Brain {
+    fn default() -> Self {
+        Brain::new()
+    }
+}
+impl Brain {
+    pub fn new() -> Brain {
+        Brain {
+            size: 0,
+            board: None,
+            search_tree: None,
+            info: Info::default(),
+        }
+    }
+
+    /// Read commands from `input` until END or end of input, writing responses to `output`
+    pub fn run<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> io::Result<()> {
+        let mut lines = input.lines();
+        while let Some(line) = lines.next() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (cmd, args) = match line.split_once(char::is_whitespace) {
+                Some((cmd, args)) => (cmd.to_uppercase(), args.trim()),
+                None => (line.to_uppercase(), ""),
+            };
+            let response = match cmd.as_str() {
+                "START" => self.start(args),
+                "RESTART" => self.restart(),
+                "BEGIN" => self.begin(),
+                "TURN" => self.turn(args),
+                "BOARD" => {
+                    let mut stones = Vec::new();
+                    for line in lines.by_ref() {
+                        let line = line?;
+                        let line = line.trim();
+                        if line.eq_ignore_ascii_case("DONE") {
+                            break;
+                        }
+                        stones.push(line.to_string());
+                    }
+                    self.board(&stones)
+                }
+                "INFO" => {
+                    self.info(args);
+                    None
+                }
+                "ABOUT" => Some(format!(
+                    "name=\"{}\", version=\"{}\", author=\"Hung Le\"",
+                    env!("CARGO_PKG_NAME"),
+                    env!("CARGO_PKG_VERSION")
+                )),
+                "END" => return Ok(()),
+                _ => Some(format!("UNKNOWN command {cmd}")),
+            };
+            if let Some(response) = response {
+                writeln!(output, "{response}")?;
+                output.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn start(&mut self, args: &str) -> Option<String> {
+        let size = match args.parse::<usize>() {
+            Ok(size) if size >= MIN_SIZE => size,
+            _ => return Some(format!("ERROR unsupported board size {args}")),
+        };
+        self.size = size;
+        self.reset();
+        Some(String::from("OK"))
+    }
+
+    fn restart(&mut self) -> Option<String> {
+        if self.size == 0 {
+            return Some(String::from("ERROR START was not received"));
+        }
+        self.reset();
+        Some(String::from("OK"))
+    }
+
+    fn reset(&mut self) {
+        let board = Board::with_rule(self.size, self.size, self.info.rule);
+        self.search_tree = Some(SearchTree::new(board.clone()));
+        self.board = Some(board);
+    }
+
+    fn begin(&mut self) -> Option<String> {
+        Some(self.play())
+    }
+
+    fn turn(&mut self, args: &str) -> Option<String> {
+        let m = match parse_coords(args) {
+            Some(m) => m,
+            None => return Some(format!("ERROR unable to parse move {args}")),
+        };
+        let (Some(board), Some(search_tree)) = (&mut self.board, &mut self.search_tree) else {
+            return Some(String::from("ERROR START was not received"));
+        };
+        if let Err(e) = board.place(m) {
+            return Some(format!("ERROR {e}"));
+        }
+        search_tree.apply_move(m);
+        Some(self.play())
+    }
+
+    fn board(&mut self, stones: &[String]) -> Option<String> {
+        if self.size == 0 {
+            return Some(String::from("ERROR START was not received"));
+        }
+        let mut own = Vec::new();
+        let mut opponent = Vec::new();
+        for stone in stones {
+            let mut fields = stone.split(',').map(|s| s.trim().parse::<usize>());
+            match (fields.next(), fields.next(), fields.next(), fields.next()) {
+                (Some(Ok(x)), Some(Ok(y)), Some(Ok(1)), None) => own.push([x, y]),
+                (Some(Ok(x)), Some(Ok(y)), Some(Ok(2)), None) => opponent.push([x, y]),
+                _ => return Some(format!("ERROR unable to parse stone {stone}")),
+            }
+        }
+
+        // the brain is always the one to move, so whoever has more stones moved first
+        let (first, second) = if own.len() == opponent.len() {
+            (own, opponent)
+        } else if own.len() + 1 == opponent.len() {
+            (opponent, own)
+        } else {
+            return Some(String::from(
+                "ERROR stone counts don't allow the brain to move",
+            ));
+        };
+        let mut board = Board::with_rule(self.size, self.size, self.info.rule);
+        for (i, &m) in first.iter().enumerate() {
+            let placed = board
+                .place(m)
+                .and_then(|_| second.get(i).map_or(Ok(()), |&m| board.place(m)));
+            if let Err(e) = placed {
+                return Some(format!("ERROR {e}"));
+            }
+        }
+        self.search_tree = Some(SearchTree::new(board.clone()));
+        self.board = Some(board);
+        Some(self.play())
+    }
+
+    fn info(&mut self, args: &str) {
+        let (key, value) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+        let value = value.trim();
+        match key.to_lowercase().as_str() {
+            "timeout_turn" => {
+                self.info.timeout_turn = value.parse().unwrap_or(DEFAULT_TIMEOUT_TURN)
+            }
+            "timeout_match" => self.info.timeout_match = value.parse().unwrap_or(0),
+            "time_left" => self.info.time_left = value.parse().ok(),
+            "rule" => {
+                let bits: u32 = value.parse().unwrap_or(0);
+                let exact_five = bits & RULE_EXACT_FIVE != 0;
+                self.info.rule = if bits & RULE_RENJU != 0 {
+                    Rule::Renju
+                } else if bits & RULE_CARO != 0 {
+                    Rule::Caro {
+                        overline_wins: !exact_five,
+                    }
+                } else if exact_five {
+                    Rule::Standard
+                } else {
+                    Rule::Freestyle
+                };
+                // INFO usually comes right after START, before any stone is down
+                if self.board.as_ref().is_some_and(|b| b.last_move().is_none()) {
+                    self.reset();
+                }
+            }
+            // the engine has no memory knob
+            "max_memory" => {}
+            _ => {}
+        }
+    }
+
+    /// Time in seconds the brain is allowed to think for the next move
+    fn time_limit(&self) -> f32 {
+        let mut budget = self.info.timeout_turn;
+        let time_left = match self.info.time_left {
+            Some(time_left) => Some(time_left),
+            None if self.info.timeout_match > 0 => Some(self.info.timeout_match),
+            None => None,
+        };
+        if let Some(time_left) = time_left {
+            budget = u64::min(budget, time_left / EXPECTED_MOVES_LEFT);
+        }
+        budget.saturating_sub(TIME_MARGIN) as f32 / 1000.0
+    }
+
+    fn play(&mut self) -> String {
+        let time_limit = self.time_limit();
+        let (Some(board), Some(search_tree)) = (&mut self.board, &mut self.search_tree) else {
+            return String::from("ERROR START was not received");
+        };
+        let m = search_tree.monte_carlo(time_limit);
+        if let Err(e) = board.place(m) {
+            return format!("ERROR {e}");
+        }
+        search_tree.apply_move(m);
+        format!("{},{}", m[0], m[1])
+    }
+}
+
+fn parse_coords(s: &str) -> Option<[usize; 2]> {
+    let (x, y) = s.split_once(',')?;
+    Some([x.trim().parse().ok()?, y.trim().parse().ok()?])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_coords, Brain};
+    use crate::board::Rule;
+
+    fn run_transcript(input: &str) -> Vec<String> {
+        let mut output = Vec::new();
+        Brain::new().run(input.as_bytes(), &mut output).unwrap();
+        String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn test_transcript() {
+        let mut brain = Brain::new();
+        let mut output = Vec::new();
+        brain
+            .run(
+                "START 15\n\
+                 INFO timeout_turn 0\n\
+                 INFO rule 0\n\
+                 BEGIN\n"
+                    .as_bytes(),
+                &mut output,
+            )
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let output: Vec<_> = output.lines().collect();
+        assert_eq!(output.len(), 2);
+        assert_eq!(output[0], "OK");
+        let first = parse_coords(output[1]).unwrap();
+        assert!(first[0] < 15 && first[1] < 15);
+
+        // reply somewhere the brain didn't play
+        let reply = if first == [0, 0] { [1, 1] } else { [0, 0] };
+        let mut output = Vec::new();
+        brain
+            .run(
+                format!(
+                    "TURN {},{}\n\
+                     RESTART\n\
+                     BOARD\n\
+                     7,7,2\n\
+                     8,8,1\n\
+                     7,8,2\n\
+                     DONE\n\
+                     ABOUT\n\
+                     END\n\
+                     START 15\n",
+                    reply[0], reply[1]
+                )
+                .as_bytes(),
+                &mut output,
+            )
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let output: Vec<_> = output.lines().collect();
+        assert_eq!(output.len(), 4);
+        let second = parse_coords(output[0]).unwrap();
+        assert_ne!(second, reply);
+        assert_ne!(first, second);
+        assert_eq!(output[1], "OK");
+        let third = parse_coords(output[2]).unwrap();
+        assert!(![[7, 7], [8, 8], [7, 8]].contains(&third));
+        assert!(output[3].starts_with("name=\"caro-ai\""));
+    }
+
+    #[test]
+    fn test_errors() {
+        let output = run_transcript(
+            "BEGIN\n\
+             START 3\n\
+             START 10\n\
+             TURN a,b\n\
+             BOARD\n\
+             1,1,1\n\
+             2,2,1\n\
+             DONE\n\
+             FOO\n",
+        );
+        assert_eq!(output.len(), 6);
+        assert!(output[0].starts_with("ERROR"));
+        assert!(output[1].starts_with("ERROR"));
+        assert_eq!(output[2], "OK");
+        assert!(output[3].starts_with("ERROR"));
+        assert!(output[4].starts_with("ERROR"));
+        assert!(output[5].starts_with("UNKNOWN"));
+    }
+
+    #[test]
+    fn test_rule_info() {
+        let mut brain = Brain::new();
+        let mut output = Vec::new();
+        brain
+            .run("START 15\nINFO rule 4\n".as_bytes(), &mut output)
+            .unwrap();
+        assert_eq!(brain.board.as_ref().unwrap().rule(), Rule::Renju);
+        brain
+            .run("INFO rule 1\nRESTART\n".as_bytes(), &mut output)
+            .unwrap();
+        assert_eq!(brain.board.as_ref().unwrap().rule(), Rule::Standard);
+        brain
+            .run("INFO rule 8\nRESTART\n".as_bytes(), &mut output)
+            .unwrap();
+        let overline_wins = true;
+        assert_eq!(
+            brain.board.as_ref().unwrap().rule(),
+            Rule::Caro { overline_wins }
+        );
+    }
+}
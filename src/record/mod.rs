@@ -0,0 +1,273 @@
+//! Game records, the stones of a game in the order they were placed.
+//!
+//! The plain text format has one field per line, empty lines and lines starting with `#` are
+//! skipped:
+//! ```text
+//! size 15 15
+//! rule caro
+//! win_length 5
+//! restriction pro
+//! moves 7,7 8,8 7,8
+//! result X
+//! ```
+//! Moves are zero-based `x,y` and may be split over several `moves` lines. `restriction` is left
+//! out when there is none, `result` is `X`, `O` or `draw` and left out while the game goes on.
+
+use std::{fmt::Display, str::FromStr};
+
+pub mod renlib;
+pub mod rif;
+pub mod sgf;
+
+use crate::board::{
+    Board, Grid, Move, Outcome, PlacingError, Player, Restriction, Rule, WIN_LENGTHS,
+};
+
+#[derive(Debug)]
+pub enum RecordError {
+    /// The text isn't a record, or describes a board that can't be set up
+    Parse(String),
+    Placing(PlacingError),
+    /// The moves end the game differently from the recorded result
+    WrongResult,
+}
+impl Display for RecordError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecordError::Parse(e) => f.write_str(e),
+            RecordError::Placing(e) => e.fmt(f),
+            RecordError::WrongResult => f.write_str("Recorded result doesn't match the moves"),
+        }
+    }
+}
+impl std::error::Error for RecordError {}
+impl From<PlacingError> for RecordError {
+    fn from(e: PlacingError) -> Self {
+        RecordError::Placing(e)
+    }
+}
+
+/// Everything needed to play a game again, written and read with `Display` and `FromStr`
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct GameRecord {
+    pub width: usize,
+    pub height: usize,
+    pub rule: Rule,
+    pub win_length: usize,
+    pub restriction: Option<Restriction>,
+    pub moves: Vec<Move>,
+    /// `None` while the game goes on
+    pub result: Option<Outcome>,
+}
+impl GameRecord {
+    /// Record of the moves placed on board so far
+    pub fn from_board<G: Grid>(board: &Board<G>) -> GameRecord {
+        GameRecord {
+            width: board.width(),
+            height: board.height(),
+            rule: board.rule(),
+            win_length: board.win_length(),
+            restriction: board.restriction(),
+            moves: board.history().collect(),
+            result: board.clone().result(),
+        }
+    }
+
+    /// Board with the recorded moves placed, undo takes them back one by one. Continue searching
+    /// from it with `SearchTree::new`
+    pub fn to_board(&self) -> Result<Board, RecordError> {
+        if self.width == 0 || self.height == 0 {
+            return Err(RecordError::Parse(String::from("Board has no points")));
+        }
+        if !WIN_LENGTHS.contains(&self.win_length)
+            || (self.rule == Rule::Renju && self.win_length != Rule::Renju.default_win_length())
+        {
+            return Err(RecordError::Parse(format!(
+                "Win length {} can't be played under {}",
+                self.win_length, self.rule
+            )));
+        }
+        let mut board =
+            Board::with_rule(self.width, self.height, self.rule).with_win_length(self.win_length);
+        if let Some(restriction) = self.restriction {
+            board = board.with_restriction(restriction);
+        }
+        for &m in &self.moves {
+            board.place(m)?;
+        }
+        if self.result.is_some() && board.result() != self.result {
+            return Err(RecordError::WrongResult);
+        }
+        Ok(board)
+    }
+}
+
+impl Display for GameRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "size {} {}", self.width, self.height)?;
+        writeln!(f, "rule {}", self.rule)?;
+        writeln!(f, "win_length {}", self.win_length)?;
+        if let Some(restriction) = self.restriction {
+            writeln!(f, "restriction {restriction}")?;
+        }
+        let moves: Vec<_> = self.moves.iter().map(|[x, y]| format!("{x},{y}")).collect();
+        writeln!(f, "moves {}", moves.join(" "))?;
+        match self.result {
+            Some(Outcome::Win(player)) => writeln!(f, "result {player:?}"),
+            Some(Outcome::Draw) => writeln!(f, "result draw"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl FromStr for GameRecord {
+    type Err = RecordError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut size = None;
+        let mut rule = Rule::default();
+        let mut win_length = None;
+        let mut restriction = None;
+        let mut moves = Vec::new();
+        let mut result = None;
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |e: &str| RecordError::Parse(format!("Line {}: {e}", i + 1));
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            let value = value.trim();
+            match key {
+                "size" => {
+                    let numbers: Result<Vec<usize>, _> =
+                        value.split_whitespace().map(str::parse).collect();
+                    match numbers.as_deref() {
+                        Ok(&[width, height]) => size = Some((width, height)),
+                        _ => return Err(error("expected a width and a height")),
+                    }
+                }
+                "rule" => rule = value.parse().map_err(|e: String| error(&e))?,
+                "win_length" => {
+                    win_length = Some(value.parse().map_err(|_| error("expected a number"))?)
+                }
+                "restriction" => restriction = Some(value.parse().map_err(|e: String| error(&e))?),
+                "moves" => {
+                    for m in value.split_whitespace() {
+                        moves.push(parse_move(m).ok_or_else(|| error(&format!("bad move {m}")))?);
+                    }
+                }
+                "result" => {
+                    result = Some(match value.to_lowercase().as_str() {
+                        "x" => Outcome::Win(Player::X),
+                        "o" => Outcome::Win(Player::O),
+                        "draw" => Outcome::Draw,
+                        _ => return Err(error("expected X, O or draw")),
+                    })
+                }
+                _ => return Err(error(&format!("unknown field {key}"))),
+            }
+        }
+        let Some((width, height)) = size else {
+            return Err(RecordError::Parse(String::from("Board size is missing")));
+        };
+        Ok(GameRecord {
+            width,
+            height,
+            rule,
+            win_length: win_length.unwrap_or(rule.default_win_length()),
+            restriction,
+            moves,
+            result,
+        })
+    }
+}
+
+/// Move written as `x,y`
+fn parse_move(s: &str) -> Option<Move> {
+    let (x, y) = s.split_once(',')?;
+    Some([x.trim().parse().ok()?, y.trim().parse().ok()?])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GameRecord, RecordError};
+    use crate::board::{Board, Outcome, PlacingError, Player, Restriction, Rule};
+
+    #[test]
+    fn test_round_trip() {
+        for rule in [Rule::default(), Rule::Renju, Rule::Connect6] {
+            let mut board = Board::with_rule(15, 15, rule);
+            while board.result().is_none() {
+                board.place_random().unwrap();
+            }
+            let text = GameRecord::from_board(&board).to_string();
+            let record: GameRecord = text.parse().unwrap();
+            assert_eq!(record, GameRecord::from_board(&board));
+            let loaded = record.to_board().unwrap();
+            assert!(loaded.history().eq(board.history()));
+            assert_eq!(loaded.hash(), board.hash());
+        }
+    }
+
+    #[test]
+    fn test_format() {
+        let board = Board::with_rule(15, 13, Rule::Standard).with_restriction(Restriction::Pro);
+        let mut board = board.with_win_length(6);
+        for m in [[7, 6], [8, 8], [7, 2]] {
+            board.place(m).unwrap();
+        }
+        let text = "size 15 13\nrule standard\nwin_length 6\nrestriction pro\nmoves 7,6 8,8 7,2\n";
+        assert_eq!(GameRecord::from_board(&board).to_string(), text);
+
+        // fields may come in any order, the win length defaults to the rule's
+        let record: GameRecord = "# a finished game\nrule connect6\n\nmoves 0,0 1,0\nmoves 1,1 \
+                                  2,2 3,3\nsize 9 9\nresult draw"
+            .parse()
+            .unwrap();
+        assert_eq!(record.win_length, 6);
+        assert_eq!(record.moves, [[0, 0], [1, 0], [1, 1], [2, 2], [3, 3]]);
+        assert_eq!(record.result, Some(Outcome::Draw));
+        assert!(matches!(record.to_board(), Err(RecordError::WrongResult)));
+        let record = GameRecord {
+            result: Some(Outcome::Win(Player::X)),
+            ..record
+        };
+        assert!(matches!(record.to_board(), Err(RecordError::WrongResult)));
+        let record = GameRecord {
+            result: None,
+            ..record
+        };
+        assert_eq!(record.to_board().unwrap().player, Player::O);
+    }
+
+    #[test]
+    fn test_errors() {
+        for text in [
+            "rule caro\nmoves 1,1",
+            "size 15\n",
+            "size 15 15\nrule gomoku",
+            "size 15 15\nmoves 1,1 2;2",
+            "size 15 15\nresult white",
+            "size 15 15\nplayer X",
+        ] {
+            assert!(matches!(
+                text.parse::<GameRecord>(),
+                Err(RecordError::Parse(_))
+            ));
+        }
+        for text in [
+            "size 15 15\nrule renju\nwin_length 6",
+            "size 15 15\nwin_length 9",
+            "size 0 15",
+        ] {
+            let record: GameRecord = text.parse().unwrap();
+            assert!(matches!(record.to_board(), Err(RecordError::Parse(_))));
+        }
+        let record: GameRecord = "size 15 15\nmoves 1,1 1,1".parse().unwrap();
+        assert!(matches!(
+            record.to_board(),
+            Err(RecordError::Placing(PlacingError::Occupied))
+        ));
+    }
+}
@@ -0,0 +1,205 @@
+//! Opening libraries of RenLib, `.lib` files holding a tree of Renju moves.
+//!
+//! The file starts with a 20 byte header, `FF "RenLib" FF`, the version and padding. Then every
+//! node of the tree follows in pre-order as a point byte and a flag byte. The point is
+//! `16 * y + x + 1` with rows counted from the top, 0 for a node without a stone. The flags say
+//! whether the node ends its line (0x80), has a sibling coming after its subtree (0x40) and
+//! carries a comment (0x08 or the older 0x20). Comments follow their node as zero terminated
+//! text in pairs of bytes. An extension (0x01) adds two more flag bytes and a board label, which
+//! is skipped. Colours alternate from Black with every stone down the tree.
+
+use super::{
+    sgf::{SgfGame, SgfNode},
+    RecordError,
+};
+use crate::board::{Move, Player, Rule};
+
+const HEADER: &[u8] = b"\xffRenLib\xff";
+const HEADER_LEN: usize = 20;
+const SIZE: usize = 15;
+
+const END_OF_LINE: u8 = 0x80;
+const SIBLING: u8 = 0x40;
+const OLD_COMMENT: u8 = 0x20;
+const COMMENT: u8 = 0x08;
+const EXTENSION: u8 = 0x01;
+
+/// Node as read, its children are indices into the nodes read so far
+struct LibNode {
+    m: Option<Move>,
+    comment: Option<String>,
+    children: Vec<usize>,
+}
+
+/// Tree of a RenLib library as a game with variations, played under Renju
+pub fn parse_renlib(bytes: &[u8]) -> Result<SgfGame, RecordError> {
+    let error = |e: &str| RecordError::Parse(format!("RenLib: {e}"));
+    if bytes.len() < HEADER_LEN || !bytes.starts_with(HEADER) {
+        return Err(error("not a RenLib file"));
+    }
+
+    // 0 is a root above the first record
+    let mut nodes = vec![LibNode {
+        m: None,
+        comment: None,
+        children: Vec::new(),
+    }];
+    let mut parent = Some(0);
+    let mut siblings_of = Vec::new();
+    let mut i = HEADER_LEN;
+    while i + 1 < bytes.len() {
+        let (point, flags) = (bytes[i], bytes[i + 1]);
+        i += 2;
+        let Some(p) = parent else {
+            return Err(error("records after the end of the tree"));
+        };
+        let m = match point {
+            0 => None,
+            point => {
+                let (x, y) = ((point as usize - 1) % 16, (point as usize - 1) / 16);
+                if x >= SIZE || y >= SIZE {
+                    return Err(error(&format!("point {point} is off the board")));
+                }
+                Some([x, y])
+            }
+        };
+        let mut comment = None;
+        if flags & EXTENSION != 0 {
+            i += 2;
+            read_text(bytes, &mut i).ok_or_else(|| error("unterminated board label"))?;
+        }
+        if flags & (COMMENT | OLD_COMMENT) != 0 {
+            comment = Some(read_text(bytes, &mut i).ok_or_else(|| error("unterminated comment"))?);
+        }
+
+        let id = nodes.len();
+        nodes.push(LibNode {
+            m,
+            comment,
+            children: Vec::new(),
+        });
+        nodes[p].children.push(id);
+        if flags & SIBLING != 0 {
+            siblings_of.push(p);
+        }
+        parent = if flags & END_OF_LINE != 0 {
+            siblings_of.pop()
+        } else {
+            Some(id)
+        };
+    }
+
+    let mut root = to_node(&nodes, 0, Player::X);
+    // libraries usually start with a node without a stone, which makes a better root
+    if let [first] = root.children.as_slice() {
+        if first.stone.is_none() && root.comment.is_none() {
+            root = root.children.remove(0);
+        }
+    }
+    Ok(SgfGame {
+        width: SIZE,
+        height: SIZE,
+        rule: Rule::Renju,
+        black: None,
+        white: None,
+        result: None,
+        root,
+    })
+}
+
+/// Node id of nodes with its subtree, player places its stone
+fn to_node(nodes: &[LibNode], id: usize, player: Player) -> SgfNode {
+    let node = &nodes[id];
+    // a node without a stone leaves the colour to the next one
+    let next = match node.m {
+        Some(_) => player.opponent(),
+        None => player,
+    };
+    SgfNode {
+        stone: node.m.map(|m| (player, m)),
+        comment: node.comment.clone(),
+        children: node
+            .children
+            .iter()
+            .map(|&child| to_node(nodes, child, next))
+            .collect(),
+    }
+}
+
+/// Zero terminated text stored in pairs of bytes, starting at i and moving i past it
+fn read_text(bytes: &[u8], i: &mut usize) -> Option<String> {
+    let start = *i;
+    loop {
+        let pair = bytes.get(*i..*i + 2)?;
+        *i += 2;
+        if pair.contains(&0) {
+            let text = &bytes[start..*i];
+            let end = text.iter().position(|&b| b == 0).unwrap();
+            return Some(String::from_utf8_lossy(&text[..end]).into_owned());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_renlib;
+    use crate::board::Player;
+    use crate::record::RecordError;
+
+    fn library(records: &[u8]) -> Vec<u8> {
+        let mut bytes = b"\xffRenLib\xff\x03\x04".to_vec();
+        bytes.resize(20, 0xff);
+        bytes.extend_from_slice(records);
+        bytes
+    }
+
+    fn point(x: u8, y: u8) -> u8 {
+        16 * y + x + 1
+    }
+
+    #[test]
+    fn test_parse_renlib() {
+        // h8, then i9 j10 with a comment, or g9
+        let bytes = library(&[
+            0x00,
+            0x00,
+            point(7, 7),
+            0x00,
+            point(8, 6),
+            0x40,
+            point(9, 5),
+            0x88,
+            b'w',
+            b'i',
+            b'n',
+            0x00,
+            point(6, 6),
+            0x80,
+        ]);
+        let game = parse_renlib(&bytes).unwrap();
+        assert_eq!(game.root.stone, None);
+        let first = &game.root.children[0];
+        assert_eq!(first.stone, Some((Player::X, [7, 7])));
+        assert_eq!(first.children.len(), 2);
+        assert_eq!(first.children[1].stone, Some((Player::O, [6, 6])));
+        let third = &first.children[0].children[0];
+        assert_eq!(third.stone, Some((Player::X, [9, 5])));
+        assert_eq!(third.comment.as_deref(), Some("win"));
+
+        let boards = game.variation_boards().unwrap();
+        let lines: Vec<Vec<_>> = boards.iter().map(|b| b.history().collect()).collect();
+        assert_eq!(lines, [vec![[7, 7], [8, 6], [9, 5]], vec![[7, 7], [6, 6]]]);
+    }
+
+    #[test]
+    fn test_errors() {
+        for bytes in [
+            b"RenLib".to_vec(),
+            library(&[point(7, 7), 0x80, point(8, 8), 0x00]),
+            library(&[0xff, 0x00]),
+            library(&[point(7, 7), 0x08, b'a', b'b']),
+        ] {
+            assert!(matches!(parse_renlib(&bytes), Err(RecordError::Parse(_))));
+        }
+    }
+}
@@ -0,0 +1,289 @@
+//! Games of the RIF XML database, as distributed by renju.net.
+//!
+//! Players, tournaments and rules are listed once and referred to by id from every `<game>`,
+//! whose `<move>` holds the stones in order as a letter for the column and the row counted from
+//! the bottom, `h8` being the centre of the 15x15 board. `bresult` is 1 when Black won, 0 when
+//! White did and 0.5 for a draw. Opening rules such as Yamaguchi or Soosorv are all played as
+//! Renju, only rules named after Gomoku are played as such.
+
+use std::collections::HashMap;
+
+use super::{GameRecord, RecordError};
+use crate::board::{Board, Move, Outcome, Player, Rule};
+
+const SIZE: usize = 15;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RifGame {
+    pub id: Option<String>,
+    pub black: Option<String>,
+    pub white: Option<String>,
+    pub tournament: Option<String>,
+    pub rule: Rule,
+    /// Winner or draw, the moves may stop before a five when a player resigned or ran out of time
+    pub result: Option<Outcome>,
+    pub moves: Vec<Move>,
+}
+impl RifGame {
+    pub fn to_board(&self) -> Result<Board, RecordError> {
+        GameRecord {
+            width: SIZE,
+            height: SIZE,
+            rule: self.rule,
+            win_length: self.rule.default_win_length(),
+            restriction: None,
+            moves: self.moves.clone(),
+            result: None,
+        }
+        .to_board()
+    }
+}
+
+/// Every game of a RIF database
+pub fn parse_rif(xml: &str) -> Result<Vec<RifGame>, RecordError> {
+    let mut players = HashMap::new();
+    let mut tournaments = HashMap::new();
+    let mut rules = HashMap::new();
+    // games refer to players that may only be listed after them, so names are filled in last
+    let mut games = Vec::new();
+    let mut game: Option<(HashMap<String, String>, Vec<Move>)> = None;
+
+    let mut tags = Tags { xml, i: 0 };
+    while let Some(tag) = tags.next_tag()? {
+        let id = || tag.attributes.get("id").cloned().unwrap_or_default();
+        match (tag.name.as_str(), tag.closing) {
+            ("player", false) => {
+                let name = [tag.attributes.get("name"), tag.attributes.get("surname")]
+                    .into_iter()
+                    .flatten()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                players.insert(id(), name.trim().to_string());
+            }
+            ("tournament", false) => {
+                tournaments.insert(
+                    id(),
+                    tag.attributes.get("name").cloned().unwrap_or_default(),
+                );
+            }
+            ("rule", false) => {
+                rules.insert(
+                    id(),
+                    rule(tag.attributes.get("name").map_or("", String::as_str)),
+                );
+            }
+            ("game", false) => game = Some((tag.attributes, Vec::new())),
+            ("move", false) => {
+                let text = tags.text();
+                if let Some((_, moves)) = &mut game {
+                    for point in text.split_whitespace() {
+                        moves.push(
+                            parse_point(point).ok_or_else(|| {
+                                RecordError::Parse(format!("RIF: bad move {point}"))
+                            })?,
+                        );
+                    }
+                }
+            }
+            ("game", true) => games.extend(game.take()),
+            _ => {}
+        }
+    }
+
+    Ok(games
+        .into_iter()
+        .map(|(attributes, moves)| {
+            let get = |key: &str| attributes.get(key).map(String::as_str);
+            let name = |key: &str| get(key).and_then(|id| players.get(id)).cloned();
+            RifGame {
+                id: get("id").map(String::from),
+                black: name("black"),
+                white: name("white"),
+                tournament: get("tournament")
+                    .and_then(|id| tournaments.get(id))
+                    .cloned(),
+                rule: get("rule")
+                    .and_then(|id| rules.get(id))
+                    .copied()
+                    .unwrap_or(Rule::Renju),
+                result: match get("bresult") {
+                    Some("1") => Some(Outcome::Win(Player::X)),
+                    Some("0") => Some(Outcome::Win(Player::O)),
+                    Some("0.5") => Some(Outcome::Draw),
+                    _ => None,
+                },
+                moves,
+            }
+        })
+        .collect())
+}
+
+/// Rule played under a RIF rule name
+fn rule(name: &str) -> Rule {
+    let name = name.to_lowercase();
+    if let Ok(rule) = name.parse() {
+        rule
+    } else if name.contains("gomoku") && name.contains("free") {
+        Rule::Freestyle
+    } else if name.contains("gomoku") {
+        Rule::Standard
+    } else {
+        Rule::Renju
+    }
+}
+
+/// Point written as a column letter and a row number from the bottom, like `h8`
+fn parse_point(point: &str) -> Option<Move> {
+    let mut chars = point.chars();
+    let column = chars.next()?.to_ascii_lowercase();
+    let x = (column as usize).checked_sub('a' as usize)?;
+    let row: usize = chars.as_str().parse().ok()?;
+    if x >= SIZE || !(1..=SIZE).contains(&row) {
+        return None;
+    }
+    Some([x, SIZE - row])
+}
+
+struct Tag {
+    name: String,
+    /// `</name>`, self-closing tags only count as opening ones
+    closing: bool,
+    attributes: HashMap<String, String>,
+}
+
+/// Just enough of an XML reader for RIF, tags with their attributes and the text after a tag
+struct Tags<'a> {
+    xml: &'a str,
+    i: usize,
+}
+impl Tags<'_> {
+    fn next_tag(&mut self) -> Result<Option<Tag>, RecordError> {
+        loop {
+            let Some(start) = self.xml[self.i..].find('<') else {
+                return Ok(None);
+            };
+            let rest = &self.xml[self.i + start..];
+            // declarations, processing instructions and comments
+            let skip_to = if rest.starts_with("<!--") {
+                Some("-->")
+            } else if rest.starts_with("<?") || rest.starts_with("<!") {
+                Some(">")
+            } else {
+                None
+            };
+            let end = match skip_to {
+                Some(end) => rest.find(end).map(|i| i + end.len()),
+                None => rest.find('>').map(|i| i + 1),
+            }
+            .ok_or_else(|| RecordError::Parse(String::from("RIF: unterminated tag")))?;
+            self.i += start + end;
+            if skip_to.is_none() {
+                return Ok(Some(parse_tag(&rest[1..end - 1])));
+            }
+        }
+    }
+
+    /// Text up to the next tag
+    fn text(&mut self) -> String {
+        let end = self.xml[self.i..]
+            .find('<')
+            .map_or(self.xml.len(), |i| self.i + i);
+        let text = unescape(&self.xml[self.i..end]);
+        self.i = end;
+        text
+    }
+}
+
+/// Tag from what is between `<` and `>`
+fn parse_tag(tag: &str) -> Tag {
+    let closing = tag.starts_with('/');
+    let tag = tag.trim_start_matches('/').trim_end_matches('/');
+    let (name, mut rest) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+    let mut attributes = HashMap::new();
+    while let Some((key, value)) = rest.split_once('=') {
+        let value = value.trim_start();
+        let Some(quote) = value.chars().next().filter(|&c| c == '"' || c == '\'') else {
+            break;
+        };
+        let Some((value, after)) = value[1..].split_once(quote) else {
+            break;
+        };
+        attributes.insert(key.trim().to_string(), unescape(value));
+        rest = after;
+    }
+    Tag {
+        name: name.to_lowercase(),
+        closing,
+        attributes,
+    }
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_point, parse_rif};
+    use crate::board::{Outcome, Player, Rule};
+
+    const DATABASE: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<database>
+  <!-- a <tiny> collection -->
+  <rules>
+    <rule id="1" name="RIF"/>
+    <rule id="2" name="Standard Gomoku"/>
+  </rules>
+  <tournaments><tournament id="7" name="World Championship &amp; Cup"/></tournaments>
+  <games>
+    <game id="10" tournament="7" rule="1" black="1" white="2" bresult="1">
+      <move>h8 a1 h9 b1 h10 c1 h11 d1 h12</move>
+    </game>
+    <game id="11" rule="2" black="2" white="1" bresult="0.5"><move>h8 h9</move></game>
+  </games>
+  <players>
+    <player id="1" name="Ana" surname="Sato" country="1"/>
+    <player id="2" name="Bo" />
+  </players>
+</database>"#;
+
+    #[test]
+    fn test_parse_rif() {
+        let games = parse_rif(DATABASE).unwrap();
+        assert_eq!(games.len(), 2);
+        let game = &games[0];
+        assert_eq!(game.id.as_deref(), Some("10"));
+        assert_eq!(game.black.as_deref(), Some("Ana Sato"));
+        assert_eq!(game.white.as_deref(), Some("Bo"));
+        assert_eq!(game.tournament.as_deref(), Some("World Championship & Cup"));
+        assert_eq!(game.rule, Rule::Renju);
+        assert_eq!(game.result, Some(Outcome::Win(Player::X)));
+        assert_eq!(game.moves[..3], [[7, 7], [0, 14], [7, 6]]);
+        let mut board = game.to_board().unwrap();
+        assert_eq!(board.result(), Some(Outcome::Win(Player::X)));
+
+        let game = &games[1];
+        assert_eq!(
+            (game.rule, game.result),
+            (Rule::Standard, Some(Outcome::Draw))
+        );
+        assert_eq!(game.black.as_deref(), Some("Bo"));
+        assert_eq!(game.tournament, None);
+    }
+
+    #[test]
+    fn test_bad_moves() {
+        assert_eq!(parse_point("a1"), Some([0, 14]));
+        assert_eq!(parse_point("o15"), Some([14, 0]));
+        for point in ["p1", "a0", "a16", "8h", ""] {
+            assert_eq!(parse_point(point), None);
+        }
+        assert!(parse_rif("<games><game><move>h8 z9</move></game></games>").is_err());
+        assert!(parse_rif("<games><game").is_err());
+    }
+}
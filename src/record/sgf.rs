@@ -0,0 +1,544 @@
+//! Smart Game Format (SGF) for Gomoku games, `GM[4]`.
+//!
+//! Black is X and White is O, points are written as two letters for x then y from the top left,
+//! `a` to `z` and then `A` to `Z`. The game properties `SZ`, `RU`, `PB`, `PW` and `RE` are read
+//! from the root node, every other node places a stone with `B` or `W` and may carry a comment
+//! `C`. `RU` holds the names `Rule` is displayed with, unknown rules read as the default one.
+//! Properties the engine has no use for are skipped, setup stones are refused since they can't be
+//! placed as moves.
+
+use std::{fmt::Display, str::FromStr};
+
+use super::{GameRecord, RecordError};
+use crate::board::{Board, BoardValue, Grid, Move, Outcome, Player, Rule};
+
+const COORDINATES: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+/// A node of the game tree
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct SgfNode {
+    /// Stone placed in this node, `None` at the root
+    pub stone: Option<(Player, Move)>,
+    pub comment: Option<String>,
+    /// Continuations, the first one is the main line
+    pub children: Vec<SgfNode>,
+}
+impl SgfNode {
+    fn new(player: Player, m: Move) -> SgfNode {
+        SgfNode {
+            stone: Some((player, m)),
+            ..SgfNode::default()
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SgfGame {
+    pub width: usize,
+    pub height: usize,
+    pub rule: Rule,
+    /// Name of the player of Black (X)
+    pub black: Option<String>,
+    /// Name of the player of White (O)
+    pub white: Option<String>,
+    /// Winner or draw, how the game was won (five, resignation, time) isn't kept
+    pub result: Option<Outcome>,
+    /// Holds the game comment, the moves start at its children
+    pub root: SgfNode,
+}
+impl SgfGame {
+    /// Game of the moves placed on board so far as a single line, boards wider or taller than
+    /// SGF has letters for are refused
+    pub fn from_board<G: Grid>(board: &Board<G>) -> Result<SgfGame, RecordError> {
+        check_size(board.width(), board.height())?;
+        let record = GameRecord::from_board(board);
+        let mut root = SgfNode::default();
+        let mut node = &mut root;
+        for m in record.moves {
+            let player = match board.get(m) {
+                Some(BoardValue::O) => Player::O,
+                _ => Player::X,
+            };
+            node.children.push(SgfNode::new(player, m));
+            node = &mut node.children[0];
+        }
+        Ok(SgfGame {
+            width: record.width,
+            height: record.height,
+            rule: record.rule,
+            black: None,
+            white: None,
+            result: record.result,
+            root,
+        })
+    }
+
+    /// Board after the main line
+    pub fn to_board(&self) -> Result<Board, RecordError> {
+        let mut board = self.empty_board()?;
+        let mut node = &self.root;
+        loop {
+            place(&mut board, node)?;
+            match node.children.first() {
+                Some(child) => node = child,
+                None => return Ok(board),
+            }
+        }
+    }
+
+    /// Board at the end of every variation, the main line first
+    pub fn variation_boards(&self) -> Result<Vec<Board>, RecordError> {
+        fn walk(
+            node: &SgfNode,
+            mut board: Board,
+            boards: &mut Vec<Board>,
+        ) -> Result<(), RecordError> {
+            place(&mut board, node)?;
+            for child in &node.children {
+                walk(child, board.clone(), boards)?;
+            }
+            if node.children.is_empty() {
+                boards.push(board);
+            }
+            Ok(())
+        }
+        let mut boards = Vec::new();
+        walk(&self.root, self.empty_board()?, &mut boards)?;
+        Ok(boards)
+    }
+
+    fn empty_board(&self) -> Result<Board, RecordError> {
+        GameRecord {
+            width: self.width,
+            height: self.height,
+            rule: self.rule,
+            win_length: self.rule.default_win_length(),
+            restriction: None,
+            moves: Vec::new(),
+            result: None,
+        }
+        .to_board()
+    }
+}
+
+/// Refuse boards with more columns or rows than there are letters for points
+fn check_size(width: usize, height: usize) -> Result<(), RecordError> {
+    if width > COORDINATES.len() || height > COORDINATES.len() {
+        return Err(RecordError::Parse(format!(
+            "{width}x{height} is too large for SGF"
+        )));
+    }
+    Ok(())
+}
+
+/// Place the stone of node, checking it has the colour of the player to move
+fn place(board: &mut Board, node: &SgfNode) -> Result<(), RecordError> {
+    if let Some((player, m)) = node.stone {
+        if player != board.player {
+            return Err(RecordError::Parse(format!(
+                "{player:?} plays at {m:?} out of turn"
+            )));
+        }
+        board.place(m)?;
+    }
+    Ok(())
+}
+
+/// Read every game of a collection, `SgfGame::from_str` reads a single one
+pub fn parse_collection(s: &str) -> Result<Vec<SgfGame>, RecordError> {
+    let mut parser = Parser { s, i: 0 };
+    let mut games = Vec::new();
+    while parser.skip_whitespace() {
+        games.push(SgfGame::from_tree(parser.game_tree()?)?);
+    }
+    Ok(games)
+}
+
+impl FromStr for SgfGame {
+    type Err = RecordError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match parse_collection(s)?.into_iter().next() {
+            Some(game) => Ok(game),
+            None => Err(RecordError::Parse(String::from("No game in the SGF"))),
+        }
+    }
+}
+
+impl Display for SgfGame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(;GM[4]FF[4]CA[UTF-8]")?;
+        if self.width == self.height {
+            write!(f, "SZ[{}]", self.width)?;
+        } else {
+            write!(f, "SZ[{}:{}]", self.width, self.height)?;
+        }
+        write!(f, "RU[{}]", self.rule)?;
+        for (id, name) in [("PB", &self.black), ("PW", &self.white)] {
+            if let Some(name) = name {
+                write!(f, "{id}[{}]", escape(name))?;
+            }
+        }
+        match self.result {
+            Some(Outcome::Win(Player::X)) => write!(f, "RE[B+]")?,
+            Some(Outcome::Win(Player::O)) => write!(f, "RE[W+]")?,
+            Some(Outcome::Draw) => write!(f, "RE[0]")?,
+            None => {}
+        }
+        write_node(f, &self.root)?;
+        writeln!(f, ")")
+    }
+}
+
+/// Properties of node and its subtree, the root's leading `;` is already written
+fn write_node(f: &mut std::fmt::Formatter<'_>, node: &SgfNode) -> std::fmt::Result {
+    let mut node = node;
+    loop {
+        if let Some((player, [x, y])) = node.stone {
+            let id = match player {
+                Player::X => "B",
+                Player::O => "W",
+            };
+            let [x, y] = [x, y].map(|c| COORDINATES[c] as char);
+            write!(f, "{id}[{x}{y}]")?;
+        }
+        if let Some(comment) = &node.comment {
+            write!(f, "C[{}]", escape(comment))?;
+        }
+        match node.children.as_slice() {
+            [] => return Ok(()),
+            [child] => {
+                write!(f, "\n;")?;
+                node = child;
+            }
+            children => {
+                for child in children {
+                    write!(f, "\n(;")?;
+                    write_node(f, child)?;
+                    write!(f, ")")?;
+                }
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace(']', "\\]")
+}
+
+/// Nodes as parsed, properties with their values and the nodes following each one
+struct RawNode {
+    properties: Vec<(String, Vec<String>)>,
+    children: Vec<RawNode>,
+}
+impl RawNode {
+    fn value(&self, id: &str) -> Option<&str> {
+        self.properties
+            .iter()
+            .find(|(property, _)| property == id)
+            .and_then(|(_, values)| values.first())
+            .map(String::as_str)
+    }
+}
+
+struct Parser<'a> {
+    s: &'a str,
+    i: usize,
+}
+impl Parser<'_> {
+    fn error(&self, e: &str) -> RecordError {
+        RecordError::Parse(format!("SGF at byte {}: {e}", self.i))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.s[self.i..].chars().next()
+    }
+
+    /// Skip whitespace, return whether anything is left
+    fn skip_whitespace(&mut self) -> bool {
+        while let Some(c) = self.peek().filter(|c| c.is_whitespace()) {
+            self.i += c.len_utf8();
+        }
+        self.peek().is_some()
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), RecordError> {
+        self.skip_whitespace();
+        if self.peek() != Some(c) {
+            return Err(self.error(&format!("expected {c}")));
+        }
+        self.i += 1;
+        Ok(())
+    }
+
+    /// `(` nodes, then the variations, `)`, returning the first node with the rest chained below
+    fn game_tree(&mut self) -> Result<RawNode, RecordError> {
+        self.expect('(')?;
+        let mut sequence = Vec::new();
+        while self.skip_whitespace() && self.peek() == Some(';') {
+            self.i += 1;
+            sequence.push(self.properties()?);
+        }
+        let mut variations = Vec::new();
+        while self.skip_whitespace() && self.peek() == Some('(') {
+            variations.push(self.game_tree()?);
+        }
+        self.expect(')')?;
+
+        let mut node = RawNode {
+            properties: sequence
+                .pop()
+                .ok_or_else(|| self.error("game tree without nodes"))?,
+            children: variations,
+        };
+        while let Some(properties) = sequence.pop() {
+            node = RawNode {
+                properties,
+                children: vec![node],
+            };
+        }
+        Ok(node)
+    }
+
+    fn properties(&mut self) -> Result<Vec<(String, Vec<String>)>, RecordError> {
+        let mut properties = Vec::new();
+        loop {
+            self.skip_whitespace();
+            let start = self.i;
+            // FF[3] allowed lower case letters in identifiers, they don't count
+            while let Some(c) = self.peek().filter(|c| c.is_ascii_alphabetic()) {
+                self.i += c.len_utf8();
+            }
+            let id: String = self.s[start..self.i]
+                .chars()
+                .filter(char::is_ascii_uppercase)
+                .collect();
+            if start == self.i {
+                return Ok(properties);
+            }
+            let mut values = Vec::new();
+            while self.skip_whitespace() && self.peek() == Some('[') {
+                values.push(self.value()?);
+            }
+            if values.is_empty() {
+                return Err(self.error(&format!("property {id} without a value")));
+            }
+            properties.push((id, values));
+        }
+    }
+
+    fn value(&mut self) -> Result<String, RecordError> {
+        self.i += 1;
+        let mut value = String::new();
+        let mut chars = self.s[self.i..].char_indices();
+        while let Some((offset, c)) = chars.next() {
+            match c {
+                ']' => {
+                    self.i += offset + 1;
+                    return Ok(value);
+                }
+                '\\' => match chars.next() {
+                    // escaped line breaks are removed
+                    Some((_, '\n')) => {}
+                    Some((_, c)) => value.push(c),
+                    None => break,
+                },
+                c => value.push(c),
+            }
+        }
+        Err(self.error("unterminated value"))
+    }
+}
+
+impl SgfGame {
+    fn from_tree(root: RawNode) -> Result<SgfGame, RecordError> {
+        let error = |e: String| RecordError::Parse(e);
+        if let Some(game) = root.value("GM").filter(|&game| game.trim() != "4") {
+            return Err(error(format!("GM[{game}] isn't a Gomoku game")));
+        }
+        let (width, height) = match root.value("SZ") {
+            None => (15, 15),
+            Some(size) => {
+                let parse = |n: &str| n.trim().parse().ok();
+                match size.split_once(':') {
+                    Some((width, height)) => parse(width).zip(parse(height)),
+                    None => parse(size).map(|n| (n, n)),
+                }
+                .ok_or_else(|| error(format!("bad size {size}")))?
+            }
+        };
+        check_size(width, height)?;
+        // other programs name their rules their own way, those fall back to the default
+        let rule = root
+            .value("RU")
+            .and_then(|rule| rule.parse().ok())
+            .unwrap_or_default();
+        let result = root.value("RE").and_then(|result| {
+            let result = result.trim();
+            if result.starts_with("B+") {
+                Some(Outcome::Win(Player::X))
+            } else if result.starts_with("W+") {
+                Some(Outcome::Win(Player::O))
+            } else if result == "0" || result.eq_ignore_ascii_case("draw") {
+                Some(Outcome::Draw)
+            } else {
+                None
+            }
+        });
+        Ok(SgfGame {
+            width,
+            height,
+            rule,
+            black: root.value("PB").map(String::from),
+            white: root.value("PW").map(String::from),
+            result,
+            root: to_node(root, [width, height])?,
+        })
+    }
+}
+
+fn to_node(raw: RawNode, [width, height]: [usize; 2]) -> Result<SgfNode, RecordError> {
+    let mut node = SgfNode {
+        comment: raw.value("C").map(String::from),
+        ..SgfNode::default()
+    };
+    for (id, values) in &raw.properties {
+        let player = match id.as_str() {
+            "B" => Player::X,
+            "W" => Player::O,
+            "AB" | "AW" | "AE" => {
+                return Err(RecordError::Parse(String::from(
+                    "Setup stones aren't supported",
+                )))
+            }
+            _ => continue,
+        };
+        let point = &values[0];
+        let coordinate = |c: u8| COORDINATES.iter().position(|&l| l == c);
+        let m = match point.as_bytes() {
+            &[x, y] => coordinate(x).zip(coordinate(y)).map(|(x, y)| [x, y]),
+            _ => None,
+        }
+        .filter(|&[x, y]| x < width && y < height)
+        .ok_or_else(|| RecordError::Parse(format!("bad point {point}")))?;
+        if node.stone.is_some() {
+            return Err(RecordError::Parse(format!(
+                "more than one stone in a node at {point}"
+            )));
+        }
+        node.stone = Some((player, m));
+    }
+    for child in raw.children {
+        node.children.push(to_node(child, [width, height])?);
+    }
+    Ok(node)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_collection, SgfGame};
+    use crate::board::{Board, Outcome, Player, Rule};
+    use crate::record::RecordError;
+
+    #[test]
+    fn test_round_trip() {
+        let mut board = Board::new(15, 15);
+        while board.result().is_none() {
+            board.place_random().unwrap();
+        }
+        let mut game = SgfGame::from_board(&board).unwrap();
+        game.black = Some(String::from("caro-ai"));
+        game.white = Some(String::from("A [bracketed] name"));
+        game.root.children[0].comment = Some(String::from("centre\\ish"));
+        let text = game.to_string();
+        assert!(text.starts_with("(;GM[4]FF[4]CA[UTF-8]SZ[15]RU[caro]PB[caro-ai]"));
+        let read: SgfGame = text.parse().unwrap();
+        assert_eq!(read, game);
+        let read_board = read.to_board().unwrap();
+        assert!(read_board.history().eq(board.history()));
+        assert_eq!(read.result, board.result());
+    }
+
+    #[test]
+    fn test_read() {
+        let text = "(;GM[4]FF[4]SZ[15]PB[Black]PW[White]RE[W+R]C[a game]
+            ;B[hh]C[start];W[ih]
+            (;B[ii]C[main\\]line];W[gg])
+            (;B[jj]))
+            (;GM[4]SZ[9:7]RU[renju];B[ed])
+            (;GM[4]RU[Gomoku])";
+        let games = parse_collection(text).unwrap();
+        assert_eq!(games.len(), 3);
+        assert_eq!((games[2].width, games[2].rule), (15, Rule::default()));
+        let game = &games[0];
+        assert_eq!(game.black.as_deref(), Some("Black"));
+        assert_eq!(game.result, Some(Outcome::Win(Player::O)));
+        assert_eq!(game.root.comment.as_deref(), Some("a game"));
+        let first = &game.root.children[0];
+        assert_eq!(first.stone, Some((Player::X, [7, 7])));
+        assert_eq!(first.comment.as_deref(), Some("start"));
+        let branch = &first.children[0];
+        assert_eq!(branch.children.len(), 2);
+        assert_eq!(branch.children[0].comment.as_deref(), Some("main]line"));
+
+        let boards = game.variation_boards().unwrap();
+        let lines: Vec<Vec<_>> = boards.iter().map(|b| b.history().collect()).collect();
+        assert_eq!(
+            lines,
+            [
+                vec![[7, 7], [8, 7], [8, 8], [6, 6]],
+                vec![[7, 7], [8, 7], [9, 9]],
+            ]
+        );
+        assert!(game.to_board().unwrap().history().eq(lines[0].clone()));
+
+        let second = &games[1];
+        assert_eq!(
+            (second.width, second.height, second.rule),
+            (9, 7, Rule::Renju)
+        );
+        assert_eq!(
+            second.to_string(),
+            "(;GM[4]FF[4]CA[UTF-8]SZ[9:7]RU[renju]\n;B[ed])\n"
+        );
+
+        // written variations read back the same
+        let again: SgfGame = game.to_string().parse().unwrap();
+        assert_eq!(&again, game);
+    }
+
+    #[test]
+    fn test_errors() {
+        for text in [
+            "(;GM[1]SZ[19];B[aa])",
+            "(;SZ[15];B[aa]",
+            "(;SZ[15]AB[aa][bb])",
+            "(;SZ[15];B[zz])",
+            "(;SZ[15];B[aa]W[bb])",
+            "(;SZ[99])",
+            "",
+        ] {
+            assert!(
+                matches!(text.parse::<SgfGame>(), Err(RecordError::Parse(_))),
+                "{text}"
+            );
+        }
+        // points past `Z` can't be written
+        let board = Board::new(53, 20);
+        assert!(matches!(
+            SgfGame::from_board(&board),
+            Err(RecordError::Parse(_))
+        ));
+        let board = Board::new(52, 52);
+        assert!(SgfGame::from_board(&board)
+            .unwrap()
+            .to_string()
+            .contains("SZ[52]"));
+
+        let game: SgfGame = "(;SZ[15];B[aa];B[bb])".parse().unwrap();
+        assert!(matches!(game.to_board(), Err(RecordError::Parse(_))));
+        let game: SgfGame = "(;SZ[15];B[aa];W[aa])".parse().unwrap();
+        assert!(matches!(game.to_board(), Err(RecordError::Placing(_))));
+    }
+}